@@ -0,0 +1,116 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{Attribute, Data, DeriveInput, Error, Fields, Lit, LitStr, Meta, NestedMeta, Path};
+
+enum Leaf {
+    Literal(LitStr),
+    Token(Path),
+}
+
+pub fn expand(input: &DeriveInput) -> Result<TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(input.generics.span(), "MarpaAst cannot be derived for generic types"));
+    }
+
+    let name = &input.ident;
+    let rules = match input.data {
+        Data::Struct(ref data) => vec![production(quote!(#name), &data.fields, &input.attrs, name.span())?],
+        Data::Enum(ref data) => {
+            let mut rules = vec![];
+            for variant in data.variants.iter() {
+                let ident = &variant.ident;
+                rules.push(production(quote!(#name::#ident), &variant.fields, &variant.attrs, ident.span())?);
+            }
+            rules
+        }
+        Data::Union(_) => return Err(Error::new(name.span(), "MarpaAst cannot be derived for unions")),
+    };
+
+    Ok(quote! {
+        impl ::marpa::ast::MarpaAst for #name {
+            fn rules(g: &mut ::marpa::ast::AstGrammar, lhs: ::marpa::grammar::Item) -> ::marpa::result::Result<()> {
+                #(#rules)*
+                Ok(())
+            }
+        }
+
+        impl ::marpa::ast::AstField for #name {
+            fn symbol(g: &mut ::marpa::ast::AstGrammar) -> ::marpa::result::Result<::marpa::grammar::Item> {
+                g.symbol::<Self>()
+            }
+
+            fn from_value(value: ::marpa::ast::AstValue) -> Self {
+                value.take()
+            }
+        }
+    })
+}
+
+// The rule for a single struct or variant, constructed with `ctor`.
+fn production(ctor: TokenStream, fields: &Fields, attrs: &[Attribute], span: Span) -> Result<TokenStream, Error> {
+    match (leaf(attrs)?, fields) {
+        (Some(Leaf::Literal(lit)), Fields::Unit) => Ok(quote! {
+            let rhs = [g.grammar().literal_string(None, #lit)?];
+            g.rule(lhs, &rhs, |_: &mut ::marpa::ast::Children| #ctor)?;
+        }),
+        (Some(Leaf::Literal(lit)), _) => Err(Error::new(lit.span(), "`literal` requires a unit struct or variant")),
+        (Some(Leaf::Token(path)), Fields::Unnamed(ref unnamed)) if unnamed.unnamed.len() == 1 => Ok(quote! {
+            let rhs = [#path(g.grammar())?];
+            g.rule(lhs, &rhs, |ch: &mut ::marpa::ast::Children| #ctor(::marpa::ast::FromText::from_text(ch.text())))?;
+        }),
+        (Some(Leaf::Token(path)), _) => Err(Error::new(
+            path.span(),
+            "`token` requires a struct or variant with a single unnamed field",
+        )),
+        (None, Fields::Unit) => Err(Error::new(
+            span,
+            "unit structs and variants need a `#[marpa(literal = \"...\")]` attribute",
+        )),
+        (None, Fields::Named(ref named)) => {
+            let tys = named.named.iter().map(|f| &f.ty);
+            let names = named.named.iter().map(|f| &f.ident);
+            Ok(quote! {
+                let rhs = [#(<#tys as ::marpa::ast::AstField>::symbol(g)?),*];
+                g.rule(lhs, &rhs, |ch: &mut ::marpa::ast::Children| #ctor { #(#names: ch.take()),* })?;
+            })
+        }
+        (None, Fields::Unnamed(ref unnamed)) => {
+            let tys = unnamed.unnamed.iter().map(|f| &f.ty);
+            let takes = unnamed.unnamed.iter().map(|_| quote!(ch.take()));
+            Ok(quote! {
+                let rhs = [#(<#tys as ::marpa::ast::AstField>::symbol(g)?),*];
+                g.rule(lhs, &rhs, |ch: &mut ::marpa::ast::Children| #ctor(#(#takes),*))?;
+            })
+        }
+    }
+}
+
+fn leaf(attrs: &[Attribute]) -> Result<Option<Leaf>, Error> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|a| a.path.is_ident("marpa")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new(meta.span(), "expected `#[marpa(...)]`")),
+        };
+        for nested in list.nested.iter() {
+            let (ident, lit) = match *nested {
+                NestedMeta::Meta(Meta::NameValue(ref nv)) => (&nv.ident, &nv.lit),
+                _ => return Err(Error::new(nested.span(), "expected `literal = \"...\"` or `token = \"...\"`")),
+            };
+            let value = match *lit {
+                Lit::Str(ref s) => s,
+                _ => return Err(Error::new(lit.span(), "expected a string literal")),
+            };
+            if found.is_some() {
+                return Err(Error::new(ident.span(), "only one of `literal` or `token` may be given"));
+            }
+            found = Some(match ident.to_string().as_str() {
+                "literal" => Leaf::Literal(value.clone()),
+                "token" => Leaf::Token(value.parse()?),
+                _ => return Err(Error::new(ident.span(), "unknown marpa attribute")),
+            });
+        }
+    }
+    Ok(found)
+}
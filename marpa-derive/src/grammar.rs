@@ -0,0 +1,403 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::collections::{HashMap, HashSet};
+use syn::parse::{Parse, ParseStream};
+use syn::{Error, Ident, LitChar, LitStr, Token, Visibility};
+
+/// The input to `marpa_grammar!`.
+pub struct GrammarDef {
+    vis: Visibility,
+    name: Ident,
+    rules: Vec<RuleDef>,
+}
+
+struct RuleDef {
+    lhs: Ident,
+    alts: Vec<Alt>,
+}
+
+struct Alt {
+    kind: AltKind,
+    name: Option<Ident>,
+}
+
+enum AltKind {
+    Seq(Vec<Elem>),
+    Repeat {
+        elem: Elem,
+        nonempty: bool,
+        // separator and whether separation is proper
+        sep: Option<(Elem, bool)>,
+    },
+}
+
+enum Elem {
+    Symbol(Ident),
+    Literal(String, Span),
+    Range(LitChar, LitChar),
+}
+
+impl Parse for GrammarDef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let vis = input.parse()?;
+        input.parse::<Token![struct]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![;]>()?;
+        let mut rules = vec![];
+        while !input.is_empty() {
+            rules.push(input.parse()?);
+        }
+        Ok(GrammarDef { vis, name, rules })
+    }
+}
+
+impl Parse for RuleDef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lhs = input.parse()?;
+        input.parse::<Token![::]>()?;
+        input.parse::<Token![=]>()?;
+        let mut alts = vec![input.parse()?];
+        while input.peek(Token![|]) {
+            input.parse::<Token![|]>()?;
+            alts.push(input.parse()?);
+        }
+        input.parse::<Token![;]>()?;
+        Ok(RuleDef { lhs, alts })
+    }
+}
+
+impl Parse for Alt {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut elems = vec![];
+        while !(input.is_empty() || input.peek(Token![|]) || input.peek(Token![;]) || input.peek(Token![=>])) {
+            let elem: Elem = input.parse()?;
+            let nonempty = if input.peek(Token![*]) {
+                input.parse::<Token![*]>()?;
+                false
+            } else if input.peek(Token![+]) {
+                input.parse::<Token![+]>()?;
+                true
+            } else {
+                elems.push(elem);
+                continue;
+            };
+            if !elems.is_empty() {
+                return Err(input.error("a repetition must be the only item in its rule"));
+            }
+            let sep = if input.peek(Token![%]) {
+                input.parse::<Token![%]>()?;
+                let proper = !input.peek(Token![%]);
+                if !proper {
+                    input.parse::<Token![%]>()?;
+                }
+                Some((input.parse()?, proper))
+            } else {
+                None
+            };
+            let name = parse_name(input)?;
+            if !(input.is_empty() || input.peek(Token![|]) || input.peek(Token![;])) {
+                return Err(input.error("a repetition must be the only item in its rule"));
+            }
+            return Ok(Alt {
+                kind: AltKind::Repeat { elem, nonempty, sep },
+                name,
+            });
+        }
+        let name = parse_name(input)?;
+        Ok(Alt {
+            kind: AltKind::Seq(elems),
+            name,
+        })
+    }
+}
+
+fn parse_name(input: ParseStream) -> syn::Result<Option<Ident>> {
+    if input.peek(Token![=>]) {
+        input.parse::<Token![=>]>()?;
+        Ok(Some(input.parse()?))
+    } else {
+        Ok(None)
+    }
+}
+
+impl Parse for Elem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Ident) {
+            Ok(Elem::Symbol(input.parse()?))
+        } else if input.peek(LitStr) {
+            let lit: LitStr = input.parse()?;
+            if lit.value().is_empty() {
+                return Err(Error::new(lit.span(), "literals must not be empty"));
+            }
+            Ok(Elem::Literal(lit.value(), lit.span()))
+        } else if input.peek(LitChar) {
+            let from: LitChar = input.parse()?;
+            if !input.peek(Token![..=]) {
+                return Ok(Elem::Literal(from.value().to_string(), from.span()));
+            }
+            input.parse::<Token![..=]>()?;
+            let to: LitChar = input.parse()?;
+            for c in [&from, &to].iter() {
+                if !c.value().is_ascii() {
+                    return Err(Error::new(c.span(), "character ranges must be ASCII"));
+                }
+            }
+            Ok(Elem::Range(from, to))
+        } else {
+            Err(input.error("expected a symbol, a string or a character range"))
+        }
+    }
+}
+
+impl Elem {
+    fn span(&self) -> Span {
+        match *self {
+            Elem::Symbol(ref ident) => ident.span(),
+            Elem::Literal(_, span) => span,
+            Elem::Range(ref from, _) => from.span(),
+        }
+    }
+
+    fn is_productive(&self, productive: &HashSet<String>) -> bool {
+        match *self {
+            Elem::Symbol(ref ident) => productive.contains(&ident.to_string()),
+            _ => true,
+        }
+    }
+}
+
+impl Alt {
+    fn elems(&self) -> Vec<&Elem> {
+        match self.kind {
+            AltKind::Seq(ref elems) => elems.iter().collect(),
+            AltKind::Repeat {
+                ref elem,
+                sep: Some((ref sep, _)),
+                ..
+            } => vec![elem, sep],
+            AltKind::Repeat { ref elem, .. } => vec![elem],
+        }
+    }
+
+    // the first element keeping this alternative from being productive
+    fn unproductive(&self, productive: &HashSet<String>) -> Option<&Elem> {
+        match self.kind {
+            AltKind::Seq(ref elems) => elems.iter().find(|e| !e.is_productive(productive)),
+            AltKind::Repeat { nonempty: false, .. } => None,
+            AltKind::Repeat { ref elem, .. } => Some(elem).filter(|e| !e.is_productive(productive)),
+        }
+    }
+}
+
+impl GrammarDef {
+    fn symbols(&self) -> Vec<&Ident> {
+        let mut seen = HashSet::new();
+        self.rules.iter().map(|r| &r.lhs).filter(|lhs| seen.insert(lhs.to_string())).collect()
+    }
+
+    // every alternative along with its lhs and the name of its field
+    fn named_rules(&self) -> Vec<(&Ident, &Alt, Ident)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let mut out = vec![];
+        for rule in self.rules.iter() {
+            for alt in rule.alts.iter() {
+                let count = counts.entry(rule.lhs.to_string()).or_insert(0);
+                let name = match alt.name {
+                    Some(ref name) => name.clone(),
+                    None => Ident::new(&format!("{}_{}", rule.lhs, count), rule.lhs.span()),
+                };
+                *count += 1;
+                out.push((&rule.lhs, alt, name));
+            }
+        }
+        out
+    }
+
+    /// Check the grammar for unknown symbols, clashing names and
+    /// unproductive rules.
+    pub fn check(&self) -> Vec<Error> {
+        let mut errors = vec![];
+        if self.rules.is_empty() {
+            errors.push(Error::new(self.name.span(), "a grammar needs at least one rule"));
+            return errors;
+        }
+
+        let defined: HashSet<String> = self.symbols().iter().map(|s| s.to_string()).collect();
+        for rule in self.rules.iter() {
+            for elem in rule.alts.iter().flat_map(|a| a.elems()) {
+                if let Elem::Symbol(ref ident) = *elem {
+                    if !defined.contains(&ident.to_string()) {
+                        errors.push(Error::new(ident.span(), format!("unknown symbol `{}`", ident)));
+                    }
+                }
+            }
+        }
+
+        let mut fields: HashSet<String> = defined.clone();
+        for (_, _, name) in self.named_rules() {
+            if !fields.insert(name.to_string()) {
+                errors.push(Error::new(name.span(), format!("the name `{}` is already used", name)));
+            }
+        }
+
+        if !errors.is_empty() {
+            return errors;
+        }
+
+        let mut productive: HashSet<String> = HashSet::new();
+        loop {
+            let before = productive.len();
+            for rule in self.rules.iter() {
+                if rule.alts.iter().any(|a| a.unproductive(&productive).is_none()) {
+                    productive.insert(rule.lhs.to_string());
+                }
+            }
+            if productive.len() == before {
+                break;
+            }
+        }
+        for rule in self.rules.iter() {
+            for elem in rule.alts.iter().filter_map(|a| a.unproductive(&productive)) {
+                let msg = format!("this rule for `{}` can never match: `{}` is unproductive", rule.lhs, quote_elem(elem));
+                errors.push(Error::new(elem.span(), msg));
+            }
+        }
+
+        errors
+    }
+
+    pub fn expand(&self) -> TokenStream {
+        let errors = self.check();
+        if !errors.is_empty() {
+            return errors.iter().map(|e| e.to_compile_error()).collect();
+        }
+
+        let vis = &self.vis;
+        let name = &self.name;
+        let symbols = self.symbols();
+        let start = symbols[0];
+        let named_rules = self.named_rules();
+
+        let mut literals: Vec<(TokenStream, Ident)> = vec![];
+        let mut item = |elem: &Elem| -> TokenStream {
+            let init = match *elem {
+                Elem::Symbol(ref ident) => return quote!(#ident),
+                Elem::Literal(ref s, _) => quote!(__marpa_g.literal_string(None, #s)?),
+                Elem::Range(ref from, ref to) => quote!(__marpa_g.char_range(None, #from, #to)?),
+            };
+            let key = init.to_string();
+            if let Some((_, ident)) = literals.iter().find(|(k, _)| k.to_string() == key) {
+                return quote!(#ident);
+            }
+            let ident = Ident::new(&format!("__marpa_lit_{}", literals.len()), Span::call_site());
+            literals.push((init, ident.clone()));
+            quote!(#ident)
+        };
+
+        let mut rules = vec![];
+        for (lhs, alt, rule) in named_rules.iter() {
+            rules.push(match alt.kind {
+                AltKind::Seq(ref elems) => {
+                    let rhs: Vec<_> = elems.iter().map(&mut item).collect();
+                    quote!(let #rule = __marpa_g.rule(Some(#lhs), &[#(#rhs),*])?;)
+                }
+                AltKind::Repeat { ref elem, nonempty, ref sep } => {
+                    let rhs = item(elem);
+                    let (sep, proper) = match *sep {
+                        Some((ref sep, proper)) => (item(sep), proper),
+                        None => (quote!(::marpa::grammar::Item::Symbol(-1)), false),
+                    };
+                    quote!(let #rule = __marpa_g.sequence(Some(#lhs), #rhs, #sep, #nonempty, #proper)?;)
+                }
+            });
+        }
+
+        let lit_inits = literals.iter().map(|(init, _)| init);
+        let lit_idents = literals.iter().map(|(_, ident)| ident);
+        let rule_names: Vec<_> = named_rules.iter().map(|(_, _, name)| name).collect();
+        let fields: Vec<_> = symbols.iter().cloned().chain(rule_names.iter().cloned()).collect();
+        let fields2 = fields.clone();
+        let fields3 = fields.clone();
+        let field_names: Vec<_> = fields.iter().map(|f| f.to_string()).collect();
+        let start_doc = format!("Add the rules of `{}` to `g`, with `{}` as the start symbol.", name, start);
+
+        quote! {
+            #[derive(Copy, Clone, Debug)]
+            #vis struct #name {
+                #(pub #fields: ::marpa::grammar::Item,)*
+            }
+
+            impl #name {
+                #[doc = #start_doc]
+                pub fn new(__marpa_g: &mut ::marpa::grammar::Grammar) -> ::marpa::result::Result<#name> {
+                    #(let #symbols = __marpa_g.new_symbol()?;)*
+                    #(let #lit_idents = #lit_inits;)*
+                    #(#rules)*
+                    #(__marpa_g.name(#fields3, #field_names);)*
+                    __marpa_g.set_start(#start)?;
+                    Ok(#name { #(#fields2),* })
+                }
+            }
+        }
+    }
+}
+
+fn quote_elem(elem: &Elem) -> String {
+    match *elem {
+        Elem::Symbol(ref ident) => ident.to_string(),
+        Elem::Literal(ref s, _) => format!("{:?}", s),
+        Elem::Range(ref from, ref to) => format!("{:?}..={:?}", from.value(), to.value()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GrammarDef;
+
+    fn errors(input: &str) -> Vec<String> {
+        let def: GrammarDef = syn::parse_str(input).unwrap();
+        def.check().iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn valid_grammar() {
+        let input = r#"
+            struct Calc;
+            expr ::= expr '+' term => add | term;
+            term ::= digit+ | "(" expr ")";
+            digit ::= '0'..='9';
+            list ::= expr* %% ',';
+        "#;
+        assert!(errors(input).is_empty());
+    }
+
+    #[test]
+    fn unknown_symbol() {
+        let input = "struct G; a ::= b 'c';";
+        assert_eq!(errors(input), vec!["unknown symbol `b`"]);
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let input = "struct G; a ::= 'x' | 'y' => a_0;";
+        assert_eq!(errors(input), vec!["the name `a_0` is already used"]);
+    }
+
+    #[test]
+    fn unproductive() {
+        let input = "struct G; a ::= b | 'x'; b ::= b 'y';";
+        assert_eq!(
+            errors(input),
+            vec![
+                "this rule for `a` can never match: `b` is unproductive",
+                "this rule for `b` can never match: `b` is unproductive",
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_repetition() {
+        assert!(syn::parse_str::<GrammarDef>("struct G; a ::= 'x' 'y'*;").is_err());
+        assert!(syn::parse_str::<GrammarDef>("struct G; a ::= 'y'* 'x';").is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Procedural macros for the [marpa] crate.
+//!
+//! `#[derive(MarpaAst)]` implements `marpa::ast::MarpaAst` and
+//! `marpa::ast::AstField` for a struct or enum. Every struct, and every
+//! variant of an enum, becomes one rule whose right-hand side is the symbols
+//! of its fields, in order. Fields may be any `AstField`, including `Box`,
+//! `Option` and `Vec` of other derived types.
+//!
+//! Two attributes describe the leaves of the grammar:
+//!
+//! * `#[marpa(literal = "...")]` on a unit struct or unit variant matches
+//!   the given string.
+//! * `#[marpa(token = "path::to::fn")]` on a struct with a single unnamed
+//!   field matches the item returned by calling the function with the
+//!   `marpa::grammar::Grammar`, and stores the matched text in the field,
+//!   which must implement `marpa::ast::FromText`.
+//!
+//! `marpa_grammar!` declares a grammar in BNF and checks it at compile time:
+//!
+//! ```ignore
+//! marpa_grammar! {
+//!     pub struct Calc;
+//!     expr ::= expr '+' term => add | term;
+//!     term ::= digit+ | "(" expr ")";
+//!     digit ::= '0'..='9';
+//! }
+//! ```
+//!
+//! Rules are alternatives of symbols, string or character literals and
+//! ASCII character ranges. An alternative holding a single item followed by
+//! `*` or `+` is a sequence, optionally separated with `% sep` (proper
+//! separation) or `%% sep` (a trailing separator is allowed). Unknown symbols,
+//! clashing names and rules that can never match are compile errors.
+//!
+//! The macro generates a `Copy` struct with a `marpa::grammar::Item` field for
+//! every symbol and every rule, and a `new` constructor adding them to a
+//! `marpa::grammar::Grammar`. Rules are named with `=> name`, or
+//! `{lhs}_{n}` for the `n`th alternative of `lhs`, and these names are
+//! registered with `Grammar::name`. The first symbol defined is the start
+//! symbol.
+//!
+//! [marpa]: https://docs.rs/marpa
+
+extern crate proc_macro;
+
+mod ast;
+mod grammar;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+#[proc_macro_derive(MarpaAst, attributes(marpa))]
+pub fn derive_marpa_ast(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match ast::expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[proc_macro]
+pub fn marpa_grammar(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as grammar::GrammarDef);
+    input.expand().into()
+}
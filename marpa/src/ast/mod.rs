@@ -0,0 +1,187 @@
+//! Typed ASTs built directly from parse values.
+//!
+//! Types implementing `MarpaAst` (usually through `#[derive(MarpaAst)]` from
+//! the `marpa-derive` crate) describe both the grammar rules that produce
+//! them and how to construct them from the values of their children.
+//! `AstGrammar` collects those rules, and the resulting `AstProcessor` is a
+//! `Processor` that evaluates a parse straight into the typed AST.
+
+use crate::grammar::{Grammar, Item};
+use crate::result::Result;
+use crate::thin::{Rule, Symbol};
+use std::any::TypeId;
+use std::collections::HashMap;
+
+mod processor;
+
+pub use self::processor::{AstProcessor, AstValue, Children};
+
+#[cfg(feature = "derive")]
+pub use marpa_derive::MarpaAst;
+
+/// A type with its own nonterminal in the grammar.
+pub trait MarpaAst: Sized + 'static {
+    /// Add the rules producing `Self` to the grammar, using `lhs` as their
+    /// left-hand side.
+    fn rules(g: &mut AstGrammar, lhs: Item) -> Result<()>;
+}
+
+/// A type that can appear as a field of a `MarpaAst` type.
+pub trait AstField: Sized + 'static {
+    /// The grammar symbol for this field, created on first use.
+    fn symbol(g: &mut AstGrammar) -> Result<Item>;
+
+    fn from_value(value: AstValue) -> Self;
+}
+
+/// Conversion from the text matched by a token.
+pub trait FromText {
+    fn from_text(text: Vec<u8>) -> Self;
+}
+
+impl FromText for Vec<u8> {
+    fn from_text(text: Vec<u8>) -> Self {
+        text
+    }
+}
+
+impl FromText for String {
+    fn from_text(text: Vec<u8>) -> Self {
+        match String::from_utf8(text) {
+            Ok(s) => s,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }
+    }
+}
+
+type Action = Box<dyn Fn(&mut Children) -> AstValue>;
+type NullAction = Box<dyn Fn() -> AstValue>;
+
+/// A `Grammar` along with the actions needed to build typed values from it.
+pub struct AstGrammar {
+    grammar: Grammar,
+    symbols: HashMap<TypeId, Item>,
+    actions: HashMap<Rule, Action>,
+    nulls: HashMap<Symbol, NullAction>,
+}
+
+impl AstGrammar {
+    pub fn new() -> Result<Self> {
+        Ok(AstGrammar {
+            grammar: Grammar::new()?,
+            symbols: Default::default(),
+            actions: Default::default(),
+            nulls: Default::default(),
+        })
+    }
+
+    /// Build the grammar for `T`, using it as the start symbol.
+    pub fn build<T: AstField>() -> Result<(Grammar, AstProcessor<T>)> {
+        let mut g = AstGrammar::new()?;
+        let start = T::symbol(&mut g)?;
+        g.grammar.set_start(start)?;
+        Ok((g.grammar, AstProcessor::new(g.actions, g.nulls)))
+    }
+
+    /// The underlying grammar, for defining byte-level tokens.
+    pub fn grammar(&mut self) -> &mut Grammar {
+        &mut self.grammar
+    }
+
+    /// The symbol for a `MarpaAst` type, adding its rules on first use.
+    pub fn symbol<T: MarpaAst>(&mut self) -> Result<Item> {
+        self.symbol_with::<T, _>(T::rules)
+    }
+
+    /// The symbol registered for the type `K`, creating it and calling
+    /// `define` with it on first use.
+    ///
+    /// The symbol is registered before `define` runs, so recursive types
+    /// refer back to it rather than recursing forever.
+    pub fn symbol_with<K, F>(&mut self, define: F) -> Result<Item>
+    where
+        K: 'static,
+        F: FnOnce(&mut AstGrammar, Item) -> Result<()>,
+    {
+        let key = TypeId::of::<K>();
+        if let Some(item) = self.symbols.get(&key) {
+            return Ok(*item);
+        }
+        let item = self.grammar.new_symbol()?;
+        self.symbols.insert(key, item);
+        define(self, item)?;
+        Ok(item)
+    }
+
+    /// Add a rule whose value is built by `action`.
+    pub fn rule<T, F>(&mut self, lhs: Item, rhs: &[Item], action: F) -> Result<Item>
+    where
+        T: 'static,
+        F: Fn(&mut Children) -> T + 'static,
+    {
+        let rule = self.grammar.rule(Some(lhs), rhs)?;
+        self.action(rule, action);
+        Ok(rule)
+    }
+
+    /// Set the action for an existing rule.
+    pub fn action<T, F>(&mut self, rule: Item, action: F)
+    where
+        T: 'static,
+        F: Fn(&mut Children) -> T + 'static,
+    {
+        self.actions.insert(rule.rule(), Box::new(move |ch| AstValue::node(action(ch))));
+    }
+
+    /// Set the value of a symbol when it matches the empty string.
+    pub fn nulled<T, F>(&mut self, sym: Item, action: F)
+    where
+        T: 'static,
+        F: Fn() -> T + 'static,
+    {
+        let sym = self.grammar.symbol(sym);
+        self.nulls.insert(sym, Box::new(move || AstValue::node(action())));
+    }
+}
+
+impl<T: AstField> AstField for Box<T> {
+    fn symbol(g: &mut AstGrammar) -> Result<Item> {
+        T::symbol(g)
+    }
+
+    fn from_value(value: AstValue) -> Self {
+        Box::new(T::from_value(value))
+    }
+}
+
+impl<T: AstField> AstField for Option<T> {
+    fn symbol(g: &mut AstGrammar) -> Result<Item> {
+        g.symbol_with::<Self, _>(|g, lhs| {
+            let inner = T::symbol(g)?;
+            g.rule(lhs, &[inner], |ch| Some(ch.take::<T>()))?;
+            g.rule(lhs, &[], |_| None::<T>)?;
+            g.nulled(lhs, || None::<T>);
+            Ok(())
+        })
+    }
+
+    fn from_value(value: AstValue) -> Self {
+        value.take()
+    }
+}
+
+impl<T: AstField> AstField for Vec<T> {
+    fn symbol(g: &mut AstGrammar) -> Result<Item> {
+        g.symbol_with::<Self, _>(|g, lhs| {
+            let inner = T::symbol(g)?;
+            let seq = g.grammar().sequence(Some(lhs), inner, Item::Symbol(-1), false, false)?;
+            g.action(seq, |ch| ch.rest::<T>());
+            g.nulled(lhs, Vec::<T>::new);
+            Ok(())
+        })
+    }
+
+    fn from_value(value: AstValue) -> Self {
+        value.take()
+    }
+}
@@ -0,0 +1,164 @@
+use crate::ast::{Action, AstField, NullAction};
+use crate::lexer::byte_scanner::ByteToken;
+use crate::stack;
+use crate::stack::processor::Processor;
+use crate::thin::{Rule, Symbol, Value};
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::vec;
+
+#[derive(Default)]
+enum Slot {
+    #[default]
+    Empty,
+    Bytes(Vec<u8>),
+    Node(Box<dyn Any>),
+    List(Vec<AstValue>),
+}
+
+/// A value on the evaluation stack of an `AstProcessor`.
+///
+/// Either the bytes matched by a part of the grammar without an action, a
+/// typed node built by an action, or the nodes of a rule without an action
+/// that has more than one.
+#[derive(Default)]
+pub struct AstValue(Slot);
+
+impl AstValue {
+    pub fn node<T: 'static>(value: T) -> Self {
+        AstValue(Slot::Node(Box::new(value)))
+    }
+
+    pub fn bytes(bytes: Vec<u8>) -> Self {
+        AstValue(Slot::Bytes(bytes))
+    }
+
+    /// Move the node out of this value.
+    ///
+    /// Panics if the value is not a node of type `T`, which means the
+    /// grammar and its actions disagree.
+    pub fn take<T: 'static>(self) -> T {
+        match self.0 {
+            Slot::Node(node) => match node.downcast::<T>() {
+                Ok(node) => *node,
+                Err(_) => panic!("AST value is not a {}", ::std::any::type_name::<T>()),
+            },
+            Slot::Bytes(_) => panic!("expected a {}, found a token", ::std::any::type_name::<T>()),
+            Slot::List(ref nodes) => panic!(
+                "expected a {}, found {} nodes from a rule without an action",
+                ::std::any::type_name::<T>(),
+                nodes.len()
+            ),
+            Slot::Empty => panic!("expected a {}, found no value", ::std::any::type_name::<T>()),
+        }
+    }
+
+    /// The bytes matched by this value, if it isn't a node.
+    pub fn text(self) -> Vec<u8> {
+        match self.0 {
+            Slot::Bytes(bytes) => bytes,
+            _ => vec![],
+        }
+    }
+
+    fn is_node(&self) -> bool {
+        matches!(self.0, Slot::Node(_) | Slot::List(_))
+    }
+}
+
+/// The values of a rule's children, in order. The nodes of a child without
+/// an action are taken as if they were children themselves.
+pub struct Children {
+    values: vec::IntoIter<AstValue>,
+}
+
+impl Children {
+    pub fn new(values: Vec<AstValue>) -> Self {
+        let mut flat = Vec::with_capacity(values.len());
+        for value in values {
+            match value.0 {
+                Slot::List(nodes) => flat.extend(nodes),
+                slot => flat.push(AstValue(slot)),
+            }
+        }
+        Children { values: flat.into_iter() }
+    }
+
+    /// Take the next child as a `T`.
+    pub fn take<T: AstField>(&mut self) -> T {
+        match self.values.next() {
+            Some(value) => T::from_value(value),
+            None => panic!("missing child for {}", ::std::any::type_name::<T>()),
+        }
+    }
+
+    /// Take all of the remaining children as `T`s.
+    pub fn rest<T: AstField>(&mut self) -> Vec<T> {
+        self.values.by_ref().map(T::from_value).collect()
+    }
+
+    /// The bytes matched by all of the remaining children.
+    pub fn text(&mut self) -> Vec<u8> {
+        let mut out = vec![];
+        for value in self.values.by_ref() {
+            out.extend(value.text());
+        }
+        out
+    }
+}
+
+/// Evaluates a parse into a `T` using the actions of an `AstGrammar`.
+///
+/// Rules without an action evaluate to the bytes they matched, or, if they
+/// have exactly one child which is a node, to that node. If more of their
+/// children are nodes, they evaluate to all of those nodes, which the
+/// action of the enclosing rule takes in order.
+pub struct AstProcessor<T> {
+    actions: HashMap<Rule, Action>,
+    nulls: HashMap<Symbol, NullAction>,
+    _root: PhantomData<fn() -> T>,
+}
+
+impl<T: AstField> AstProcessor<T> {
+    pub(crate) fn new(actions: HashMap<Rule, Action>, nulls: HashMap<Symbol, NullAction>) -> Self {
+        AstProcessor {
+            actions,
+            nulls,
+            _root: PhantomData,
+        }
+    }
+
+    /// Evaluate a parse value into the AST.
+    pub fn eval(self, value: Value) -> T {
+        T::from_value(stack::proc_value(self, value))
+    }
+}
+
+impl<T> Processor for AstProcessor<T> {
+    type Token = ByteToken;
+    type Tree = AstValue;
+
+    fn proc_rule(&mut self, rule: Rule, children: Vec<AstValue>) -> AstValue {
+        if let Some(action) = self.actions.get(&rule) {
+            return action(&mut Children::new(children));
+        }
+
+        match children.iter().filter(|ch| ch.is_node()).count() {
+            0 => AstValue::bytes(Children::new(children).text()),
+            1 => children.into_iter().find(AstValue::is_node).unwrap(),
+            _ => AstValue(Slot::List(Children::new(children).values.filter(AstValue::is_node).collect())),
+        }
+    }
+
+    fn proc_token(&mut self, tok: ByteToken) -> AstValue {
+        AstValue::bytes(vec![*tok])
+    }
+
+    fn proc_null(&mut self, sym: Symbol) -> AstValue {
+        match self.nulls.get(&sym) {
+            Some(action) => action(),
+            None => AstValue::default(),
+        }
+    }
+}
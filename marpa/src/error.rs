@@ -1,65 +1,218 @@
 #![allow(unused)]
 
-pub struct Error(u32, Option<String>);
+use crate::thin::EarleySet;
+use libmarpa_sys::*;
+use std::error;
+use std::fmt;
 
-impl Error {
-    pub fn get_code(&self) -> u32 {
-        self.0
+/// An error from libmarpa or from the layers built on it. libmarpa errors
+/// are sorted by what their code is about, and keep the code.
+#[derive(Debug)]
+pub enum Error {
+    /// Building a grammar, or any libmarpa error not in another class.
+    Grammar(u32),
+    /// Precomputing a grammar, such as a cycle or an unproductive start.
+    Precompute(u32),
+    /// Reading input into a recognizer.
+    Recognizer(u32),
+    /// Building and iterating bocages, orders, trees and values.
+    Valuation(u32),
+    /// The input doesn't parse at `location`.
+    Parse { location: Position, message: String },
+    /// A resource limit from `parser::Limits` was exceeded.
+    Limit(Limit),
+    /// The callback given to `Parser::earley_item_threshold` aborted the
+    /// parse at Earley set `set`, which had `items` items.
+    EarleyItemThreshold { set: EarleySet, items: i32 },
+    /// A parse was stopped with a `parser::CancelToken`.
+    Cancelled,
+    /// Any other failure, such as an argument the API can't use or a call
+    /// out of order, described by its message.
+    Message(String),
+    /// A message about an error from elsewhere, which is kept as the source.
+    Wrapped(String, Box<dyn error::Error + Send + Sync>),
+}
+
+/// Where a parse error is in the input, in the units of the parser that
+/// found it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Position {
+    /// An earleme of a `Parser`, which is the number of tokens read before.
+    Earleme(usize),
+    /// A byte offset into the input of a `ScanlessParser`.
+    Byte(usize),
+    /// The index of a token read from the source of a `lexer::layout`
+    /// adapter.
+    Token(usize),
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Position::Earleme(n) => write!(f, "earleme {}", n),
+            Position::Byte(n) => write!(f, "byte {}", n),
+            Position::Token(n) => write!(f, "token {}", n),
+        }
     }
 }
 
-impl ::std::error::Error for Error {
-    fn description(&self) -> &str {
-        match self.0 as usize {
-            i if i < MARPA_ERROR_DESCRIPTION.len() => MARPA_ERROR_DESCRIPTION[i].2,
-            _ => match self.1 {
-                Some(ref s) => s,
-                _ => "undefined error",
-            },
+/// A resource limit from `parser::Limits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    InputLength,
+    EarleyItems,
+    EarleySetItems,
+    Parses,
+    TreeDepth,
+    Deadline,
+}
+
+impl Error {
+    pub fn wrap<E: error::Error + Send + Sync + 'static>(message: impl Into<String>, source: E) -> Error {
+        Error::Wrapped(message.into(), Box::new(source))
+    }
+
+    /// The libmarpa error code, if this error came from libmarpa.
+    pub fn code(&self) -> Option<u32> {
+        match *self {
+            Error::Grammar(code) | Error::Precompute(code) | Error::Recognizer(code) | Error::Valuation(code) => Some(code),
+            _ => None,
+        }
+    }
+
+    /// The limit that was exceeded, if that's what this error is about.
+    pub fn limit(&self) -> Option<Limit> {
+        match *self {
+            Error::Limit(limit) => Some(limit),
+            _ => None,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(*self, Error::Cancelled)
+    }
+
+    // Whether a recognizer refused a token that its grammar doesn't accept
+    // where it was read.
+    pub(crate) fn is_rejected_token(&self) -> bool {
+        match *self {
+            Error::Recognizer(code) => {
+                code == MARPA_ERR_UNEXPECTED_TOKEN_ID || code == MARPA_ERR_INACCESSIBLE_TOKEN || code == MARPA_ERR_NO_TOKEN_EXPECTED_HERE
+            }
+            _ => false,
         }
     }
 }
 
-impl ::std::fmt::Display for Error {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        use std::error::Error;
-        write!(f, "{}", self.description())
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Wrapped(_, ref source) => Some(&**source),
+            _ => None,
+        }
     }
 }
 
-impl ::std::fmt::Debug for Error {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        write!(f, "{}", self)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Grammar(code) | Error::Precompute(code) | Error::Recognizer(code) | Error::Valuation(code) => {
+                match MARPA_ERROR_DESCRIPTION.get(code as usize) {
+                    Some(desc) => write!(f, "{}", desc.2),
+                    None => write!(f, "undefined error: {}", code),
+                }
+            }
+            Error::Parse { location, ref message } => write!(f, "parse error at {}: {}", location, message),
+            Error::Limit(limit) => {
+                let what = match limit {
+                    Limit::InputLength => "input length",
+                    Limit::EarleyItems => "Earley items",
+                    Limit::EarleySetItems => "Earley items per set",
+                    Limit::Parses => "parse count",
+                    Limit::TreeDepth => "tree depth",
+                    Limit::Deadline => "deadline",
+                };
+                write!(f, "{} limit exceeded", what)
+            }
+            Error::EarleyItemThreshold { set, items } => {
+                write!(f, "Earley set {} has {} items, over the threshold", set, items)
+            }
+            Error::Cancelled => write!(f, "parse cancelled"),
+            Error::Message(ref message) => write!(f, "{}", message),
+            Error::Wrapped(ref message, ref source) => write!(f, "{}: {}", message, source),
+        }
     }
 }
 
 impl From<u32> for Error {
-    fn from(other: u32) -> Error {
-        match other {
-            i if (i as usize) < MARPA_ERROR_DESCRIPTION.len() => Error(i, None),
-            i => Error(i, Some(format!("undefined error: {}", i))),
+    fn from(code: u32) -> Error {
+        match code {
+            MARPA_ERR_COUNTED_NULLABLE
+            | MARPA_ERR_GRAMMAR_HAS_CYCLE
+            | MARPA_ERR_INVALID_START_SYMBOL
+            | MARPA_ERR_NOT_PRECOMPUTED
+            | MARPA_ERR_NO_RULES
+            | MARPA_ERR_NO_START_SYMBOL
+            | MARPA_ERR_NULLING_TERMINAL
+            | MARPA_ERR_START_NOT_LHS
+            | MARPA_ERR_UNPRODUCTIVE_START => Error::Precompute(code),
+            MARPA_ERR_DUPLICATE_TOKEN
+            | MARPA_ERR_YIM_COUNT
+            | MARPA_ERR_EVENT_IX_NEGATIVE
+            | MARPA_ERR_EVENT_IX_OOB
+            | MARPA_ERR_INACCESSIBLE_TOKEN
+            | MARPA_ERR_INVALID_LOCATION
+            | MARPA_ERR_NO_EARLEY_SET_AT_LOCATION
+            | MARPA_ERR_NO_TOKEN_EXPECTED_HERE
+            | MARPA_ERR_PARSE_EXHAUSTED
+            | MARPA_ERR_PARSE_TOO_LONG
+            | MARPA_ERR_PROGRESS_REPORT_EXHAUSTED
+            | MARPA_ERR_PROGRESS_REPORT_NOT_STARTED
+            | MARPA_ERR_RECCE_NOT_ACCEPTING_INPUT
+            | MARPA_ERR_RECCE_NOT_STARTED
+            | MARPA_ERR_RECCE_STARTED
+            | MARPA_ERR_TOKEN_IS_NOT_TERMINAL
+            | MARPA_ERR_TOKEN_LENGTH_LE_ZERO
+            | MARPA_ERR_TOKEN_TOO_LONG
+            | MARPA_ERR_UNEXPECTED_TOKEN_ID
+            | MARPA_ERR_RECCE_IS_INCONSISTENT => Error::Recognizer(code),
+            MARPA_ERR_ANDID_NEGATIVE
+            | MARPA_ERR_ANDID_NOT_IN_OR
+            | MARPA_ERR_ANDIX_NEGATIVE
+            | MARPA_ERR_BOCAGE_ITERATION_EXHAUSTED
+            | MARPA_ERR_DUPLICATE_AND_NODE
+            | MARPA_ERR_NOOKID_NEGATIVE
+            | MARPA_ERR_NO_AND_NODES
+            | MARPA_ERR_NO_OR_NODES
+            | MARPA_ERR_NO_PARSE
+            | MARPA_ERR_ORDER_FROZEN
+            | MARPA_ERR_ORID_NEGATIVE
+            | MARPA_ERR_OR_ALREADY_ORDERED
+            | MARPA_ERR_TREE_EXHAUSTED
+            | MARPA_ERR_TREE_PAUSED
+            | MARPA_ERR_VALUATOR_INACTIVE
+            | MARPA_ERR_BEFORE_FIRST_TREE => Error::Valuation(code),
+            // including MARPA_ERR_PRECOMPUTED, from changing a precomputed grammar
+            _ => Error::Grammar(code),
         }
     }
 }
 
 impl<'a> From<&'a str> for Error {
     fn from(other: &str) -> Error {
-        other.into()
+        Error::Message(other.to_string())
     }
 }
 
 impl From<String> for Error {
     fn from(other: String) -> Error {
-        Error(std::u32::MAX, Some(other))
+        Error::Message(other)
     }
 }
 
-impl ::std::ops::Deref for Error {
-    type Target = str;
-
-    fn deref(&self) -> &str {
-        use std::error::Error;
-        self.description()
+impl From<Limit> for Error {
+    fn from(limit: Limit) -> Error {
+        Error::Limit(limit)
     }
 }
 
@@ -176,3 +329,43 @@ const MARPA_ERROR_DESCRIPTION: &[MarpaDescription] = &[
     (98, "MARPA_ERR_HEADERS_DO_NOT_MATCH", "Internal error: Libmarpa was built incorrectly"),
     (99, "MARPA_ERR_NOT_A_SEQUENCE", "Rule is not a sequence"),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn classes() {
+        let err = Error::from(17);
+        assert!(matches!(err, Error::Precompute(17)));
+        assert_eq!(err.code(), Some(17));
+        assert_eq!(err.to_string(), "Grammar has cycle");
+        assert!(matches!(Error::from(44), Error::Recognizer(44)));
+        assert!(matches!(Error::from(MARPA_ERR_INACCESSIBLE_TOKEN), Error::Recognizer(_)));
+        assert!(matches!(Error::from(MARPA_ERR_PRECOMPUTED), Error::Grammar(_)));
+        assert!(matches!(Error::from(41), Error::Valuation(41)));
+        assert!(matches!(Error::from(90), Error::Grammar(90)));
+        assert_eq!(Error::from(1000).to_string(), "undefined error: 1000");
+        assert_eq!(Error::from("oops").code(), None);
+    }
+
+    #[test]
+    fn source() {
+        let err = Error::wrap("bad number", "x".parse::<i32>().unwrap_err());
+        assert!(err.to_string().starts_with("bad number: "));
+        assert!(err.source().unwrap().is::<std::num::ParseIntError>());
+        assert!(Error::from(Limit::Parses).source().is_none());
+    }
+
+    #[test]
+    fn positions() {
+        let err = Error::Parse {
+            location: Position::Byte(3),
+            message: "no lexeme".into(),
+        };
+        assert_eq!(err.to_string(), "parse error at byte 3: no lexeme");
+        assert_eq!(Position::Earleme(2).to_string(), "earleme 2");
+        assert_eq!(Position::Token(8).to_string(), "token 8");
+    }
+}
@@ -0,0 +1,244 @@
+use crate::grammar::{RuleInfo, Snapshot};
+use crate::thin::Symbol;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Nullable, FIRST and FOLLOW sets for the symbols of a grammar.
+///
+/// Terminals are the symbols without rules of their own, along with those
+/// explicitly marked as terminals. FIRST and FOLLOW sets only contain
+/// terminals, and FIRST of a terminal is the terminal itself.
+#[derive(Clone, Debug)]
+pub struct GrammarAnalysis {
+    terminals: BTreeSet<Symbol>,
+    nullable: HashSet<Symbol>,
+    first: HashMap<Symbol, BTreeSet<Symbol>>,
+    follow: HashMap<Symbol, BTreeSet<Symbol>>,
+    ends: HashSet<Symbol>,
+}
+
+impl GrammarAnalysis {
+    pub(crate) fn new(snapshot: &Snapshot) -> GrammarAnalysis {
+        let rules = &snapshot.rules;
+        let lhs: HashSet<Symbol> = rules.iter().map(|r| r.lhs).collect();
+        let terminals: BTreeSet<Symbol> = (0..snapshot.symbols.len() as Symbol)
+            .filter(|s| !lhs.contains(s) || snapshot.symbols[*s as usize].terminal)
+            .collect();
+
+        let nullable = fixpoint(rules, |rule, nullable| match rule.sequence {
+            Some(ref seq) => seq.min == 0 || nullable.contains(&rule.rhs[0]),
+            None => rule.rhs.iter().all(|s| nullable.contains(s)),
+        });
+
+        let mut first: HashMap<Symbol, BTreeSet<Symbol>> = terminals.iter().map(|&t| (t, Some(t).into_iter().collect())).collect();
+        loop {
+            let mut changed = false;
+            for rule in rules {
+                for sym in starts(rule, &nullable) {
+                    if let Some(add) = first.get(&sym).cloned() {
+                        changed |= extend(&mut first, rule.lhs, &add);
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let adjacent: Vec<_> = rules.iter().map(|rule| adjacency(rule, &nullable)).collect();
+        let mut follow: HashMap<Symbol, BTreeSet<Symbol>> = HashMap::new();
+        let mut ends: HashSet<Symbol> = snapshot.start.into_iter().collect();
+        loop {
+            let mut changed = false;
+            for (rule, (pairs, lasts)) in rules.iter().zip(&adjacent) {
+                for &(sym, next) in pairs {
+                    if let Some(add) = first.get(&next) {
+                        changed |= extend(&mut follow, sym, add);
+                    }
+                }
+                for &sym in lasts {
+                    if let Some(add) = follow.get(&rule.lhs).cloned() {
+                        changed |= extend(&mut follow, sym, &add);
+                    }
+                    if ends.contains(&rule.lhs) {
+                        changed |= ends.insert(sym);
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        GrammarAnalysis {
+            terminals,
+            nullable,
+            first,
+            follow,
+            ends,
+        }
+    }
+
+    pub fn is_terminal(&self, sym: Symbol) -> bool {
+        self.terminals.contains(&sym)
+    }
+
+    /// Whether `sym` can match the empty string.
+    pub fn is_nullable(&self, sym: Symbol) -> bool {
+        self.nullable.contains(&sym)
+    }
+
+    /// The terminals that can start `sym`, in order.
+    pub fn first(&self, sym: Symbol) -> impl Iterator<Item = Symbol> + '_ {
+        self.first.get(&sym).into_iter().flatten().cloned()
+    }
+
+    /// The terminals that can come right after `sym`, in order.
+    pub fn follow(&self, sym: Symbol) -> impl Iterator<Item = Symbol> + '_ {
+        self.follow.get(&sym).into_iter().flatten().cloned()
+    }
+
+    /// Whether `sym` can come at the very end of the input.
+    pub fn can_end(&self, sym: Symbol) -> bool {
+        self.ends.contains(&sym)
+    }
+
+    /// The bytes that can start `sym`, for grammars built on the byte
+    /// symbols of `grammar::Grammar`.
+    pub fn first_bytes(&self, sym: Symbol) -> Vec<u8> {
+        self.first(sym).filter(|s| (0..256).contains(s)).map(|s| s as u8).collect()
+    }
+}
+
+// The RHS symbols that can start a rule.
+fn starts(rule: &RuleInfo, nullable: &HashSet<Symbol>) -> Vec<Symbol> {
+    match rule.sequence {
+        Some(ref seq) => {
+            let item = rule.rhs[0];
+            match seq.separator {
+                Some(sep) if nullable.contains(&item) => vec![item, sep],
+                _ => vec![item],
+            }
+        }
+        None => {
+            let end = rule.rhs.iter().position(|s| !nullable.contains(s)).map_or(rule.rhs.len(), |i| i + 1);
+            rule.rhs[..end].to_vec()
+        }
+    }
+}
+
+// The pairs of RHS symbols where the second can come right after the first,
+// and the RHS symbols that can come last.
+fn adjacency(rule: &RuleInfo, nullable: &HashSet<Symbol>) -> (Vec<(Symbol, Symbol)>, Vec<Symbol>) {
+    let (mut pairs, mut lasts) = (vec![], vec![]);
+    match rule.sequence {
+        Some(ref seq) => {
+            let item = rule.rhs[0];
+            lasts.push(item);
+            match seq.separator {
+                Some(sep) => {
+                    pairs.push((item, sep));
+                    pairs.push((sep, item));
+                    if nullable.contains(&sep) {
+                        pairs.push((item, item));
+                    }
+                    if nullable.contains(&item) {
+                        pairs.push((sep, sep));
+                    }
+                    if !seq.proper || nullable.contains(&item) {
+                        lasts.push(sep);
+                    }
+                }
+                None => pairs.push((item, item)),
+            }
+        }
+        None => {
+            for (i, &sym) in rule.rhs.iter().enumerate() {
+                let rest = &rule.rhs[i + 1..];
+                for &next in rest {
+                    pairs.push((sym, next));
+                    if !nullable.contains(&next) {
+                        break;
+                    }
+                }
+                if rest.iter().all(|s| nullable.contains(s)) {
+                    lasts.push(sym);
+                }
+            }
+        }
+    }
+    (pairs, lasts)
+}
+
+// Adds `add` to the set for `to`, returning whether it grew.
+fn extend(sets: &mut HashMap<Symbol, BTreeSet<Symbol>>, to: Symbol, add: &BTreeSet<Symbol>) -> bool {
+    let set = sets.entry(to).or_default();
+    let before = set.len();
+    set.extend(add);
+    set.len() != before
+}
+
+/// The LHS symbols of rules that eventually satisfy `holds`.
+fn fixpoint<F>(rules: &[RuleInfo], holds: F) -> HashSet<Symbol>
+where
+    F: Fn(&RuleInfo, &HashSet<Symbol>) -> bool,
+{
+    let mut set = HashSet::new();
+    loop {
+        let before = set.len();
+        for rule in rules {
+            if !set.contains(&rule.lhs) && holds(rule, &set) {
+                set.insert(rule.lhs);
+            }
+        }
+        if set.len() == before {
+            return set;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::{Grammar, Item};
+
+    #[test]
+    fn first_follow() {
+        // expr ::= term tail
+        // tail ::= '+' term tail | ()
+        // term ::= [0-9] | '(' expr ')'
+        // list ::= expr+ % ','
+        let mut g = Grammar::new().unwrap();
+        let expr = g.new_symbol().unwrap();
+        let tail = g.new_symbol().unwrap();
+        let term = g.new_symbol().unwrap();
+        let digit = g.byte_range(None, b'0', b'9').unwrap();
+        let byte = |b: u8| Item::Symbol(i32::from(b));
+        let (plus, open, close, comma) = (byte(b'+'), byte(b'('), byte(b')'), byte(b','));
+        g.rule(Some(expr), &[term, tail]).unwrap();
+        g.rule(Some(tail), &[plus, term, tail]).unwrap();
+        g.rule(Some(tail), &[]).unwrap();
+        g.rule(Some(term), &[digit]).unwrap();
+        g.rule(Some(term), &[open, expr, close]).unwrap();
+        let list = g.sequence(None, expr, comma, true, true).unwrap();
+        g.set_start(list).unwrap();
+
+        let a = g.analysis().unwrap();
+        let (expr, tail, term) = (g.symbol(expr), g.symbol(tail), g.symbol(term));
+        let digits: Vec<u8> = (b'0'..=b'9').collect();
+        let first_expr: Vec<u8> = Some(b'(').into_iter().chain(digits.clone()).collect();
+
+        assert!(a.is_nullable(tail));
+        assert!(!a.is_nullable(expr));
+        assert!(a.is_terminal(i32::from(b'+')));
+        assert!(!a.is_terminal(term));
+        assert_eq!(a.first_bytes(expr), first_expr);
+        assert_eq!(a.first_bytes(g.symbol(digit)), digits);
+        assert_eq!(a.first_bytes(tail), b"+");
+        assert_eq!(a.first_bytes(g.symbol(list)), first_expr);
+
+        let follow = |sym| a.follow(sym).map(|s| s as u8).collect::<Vec<_>>();
+        assert_eq!(follow(term), b")+,");
+        assert_eq!(follow(expr), b"),");
+        assert_eq!(follow(tail), b"),");
+        assert!(a.can_end(term) && a.can_end(tail) && !a.can_end(i32::from(b'(')));
+    }
+}
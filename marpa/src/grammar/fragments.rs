@@ -0,0 +1,77 @@
+use crate::grammar::{Grammar, Item};
+use crate::result::Result;
+
+/// Builders for common shapes of rules, parameterized over the items they're
+/// made of. Each use makes fresh symbols, and returns the outer rule.
+impl Grammar {
+    /// `item (sep item)*`, with no trailing separator.
+    pub fn separated_nonempty(&mut self, lhs: Option<Item>, item: Item, sep: Item) -> Result<Item> {
+        self.sequence(lhs, item, sep, true, true)
+    }
+
+    /// `open inner close`
+    pub fn bracketed(&mut self, lhs: Option<Item>, open: Item, inner: Item, close: Item) -> Result<Item> {
+        self.rule(lhs, &[open, inner, close])
+    }
+
+    /// `open (item (sep item)*)? close`, where the list may end with a
+    /// separator if `trailing_sep` is set.
+    pub fn delimited(&mut self, lhs: Option<Item>, open: Item, item: Item, sep: Item, close: Item, trailing_sep: bool) -> Result<Item> {
+        let list = self.sequence(None, item, sep, true, !trailing_sep)?;
+        let inner = self.maybe(None, list)?;
+        self.bracketed(lhs, open, inner, close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grammar::{Grammar, Item};
+    use crate::lexer::byte_scanner::ByteScanner;
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    fn parses<F>(build: F, input: &str) -> bool
+    where
+        F: FnOnce(&mut Grammar, Item) -> Item,
+    {
+        let mut g = Grammar::new().unwrap();
+        let digit = g.byte_range(None, b'0', b'9').unwrap();
+        let start = build(&mut g, digit);
+        g.set_start(start).unwrap();
+        let mut p = Parser::with_grammar(g.unwrap());
+        p.run_recognizer(ByteScanner::new(Cursor::new(input))).is_ok()
+    }
+
+    fn byte(b: u8) -> Item {
+        Item::Symbol(i32::from(b))
+    }
+
+    #[test]
+    fn fragments() {
+        let list = |g: &mut Grammar, digit| g.separated_nonempty(None, digit, byte(b',')).unwrap();
+        assert!(parses(list, "1,2,3"));
+        assert!(!parses(list, ""));
+        assert!(!parses(list, "1,2,"));
+
+        let paren = |g: &mut Grammar, digit| g.bracketed(None, byte(b'('), digit, byte(b')')).unwrap();
+        assert!(parses(paren, "(1)"));
+        assert!(!parses(paren, "()"));
+
+        for &trailing in &[false, true] {
+            let array = |g: &mut Grammar, digit| g.delimited(None, byte(b'['), digit, byte(b','), byte(b']'), trailing).unwrap();
+            assert!(parses(array, "[]"));
+            assert!(parses(array, "[1,2]"));
+            assert!(!parses(array, "[,]"));
+            assert!(!parses(array, "[1,,2]"));
+            assert_eq!(parses(array, "[1,2,]"), trailing);
+        }
+
+        // fragments can be nested, each use with its own symbols
+        let nested = |g: &mut Grammar, digit| {
+            let row = g.delimited(None, byte(b'['), digit, byte(b','), byte(b']'), false).unwrap();
+            g.delimited(None, byte(b'['), row, byte(b','), byte(b']'), true).unwrap()
+        };
+        assert!(parses(nested, "[[1],[],[2,3],]"));
+        assert!(!parses(nested, "[1]"));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::grammar::{Grammar, Names, RuleInfo, Snapshot};
+use crate::result::Result;
+use crate::thin::{Event, Rule, Symbol};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A problem found by `Grammar::lint`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lint {
+    /// The grammar has no start symbol.
+    NoStart,
+    /// A symbol with rules of its own that can't be reached from the start
+    /// symbol.
+    InaccessibleSymbol(Symbol),
+    /// A rule that can never match any input.
+    UnproductiveRule(Rule),
+    /// The rules through which symbols can derive themselves without
+    /// consuming any input.
+    Cycle(Vec<Rule>),
+    /// A symbol marked as a terminal that can also match the empty string.
+    NullingTerminal(Symbol),
+    /// A nullable symbol used as the item or separator of a sequence.
+    CountedNullable(Symbol),
+    /// A rule with the same LHS and RHS as an earlier rule. libmarpa refuses
+    /// to create these, so they only come up in a `Snapshot`.
+    DuplicateRule(Rule, Rule),
+    /// Precomputing the grammar failed. The other lints usually say why.
+    Precompute(String),
+}
+
+/// The lints for a grammar, along with its names for display.
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub lints: Vec<Lint>,
+    names: Names,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.lints.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rule = |r: Rule| match self.names.rule(r) {
+            Some(name) => format!("rule {} ({})", r, name),
+            None => format!("rule {}", r),
+        };
+        let symbol = |s: Symbol| match self.names.symbol(s) {
+            Some(name) => format!("symbol {} ({})", s, name),
+            None => format!("symbol {}", s),
+        };
+        for lint in &self.lints {
+            match *lint {
+                Lint::NoStart => writeln!(f, "no start symbol")?,
+                Lint::InaccessibleSymbol(s) => writeln!(f, "{} is not accessible from the start symbol", symbol(s))?,
+                Lint::UnproductiveRule(r) => writeln!(f, "{} is unproductive", rule(r))?,
+                Lint::Cycle(ref rules) => {
+                    let rules: Vec<_> = rules.iter().map(|&r| rule(r)).collect();
+                    writeln!(f, "cycle through {}", rules.join(", "))?
+                }
+                Lint::NullingTerminal(s) => writeln!(f, "{} is a terminal but can be nulled", symbol(s))?,
+                Lint::CountedNullable(s) => writeln!(f, "{} is nullable but counted in a sequence", symbol(s))?,
+                Lint::DuplicateRule(first, dup) => writeln!(f, "{} duplicates {}", rule(dup), rule(first))?,
+                Lint::Precompute(ref err) => writeln!(f, "precompute failed: {}", err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Check the grammar in this snapshot for problems, like `Grammar::lint`.
+    pub fn lint(&self) -> Result<Report> {
+        let mut names = Names::new();
+        for (id, sym) in self.symbols.iter().enumerate() {
+            if let Some(ref name) = sym.name {
+                names.set_symbol(id as Symbol, name.clone());
+            }
+        }
+        for (id, rule) in self.rules.iter().enumerate() {
+            if let Some(ref name) = rule.name {
+                names.set_rule(id as Rule, name.clone());
+            }
+        }
+        report(self, names)
+    }
+}
+
+pub(crate) fn report(snapshot: &Snapshot, names: Names) -> Result<Report> {
+    let mut lints = duplicates(&snapshot.rules);
+    // libmarpa won't create duplicate rules, so nothing else can be checked
+    if !lints.is_empty() {
+        return Ok(Report { lints, names });
+    }
+    let start = match snapshot.start {
+        Some(start) => start,
+        None => {
+            lints.push(Lint::NoStart);
+            return Ok(Report { lints, names });
+        }
+    };
+
+    let precomputed = Grammar::from_snapshot(snapshot)?.internal.precompute();
+
+    // libmarpa only analyzes a grammar that precomputes, and an unproductive
+    // start stops that, so the analysis runs on a copy with a new start that
+    // can also match a fresh terminal
+    let mut g = Grammar::from_snapshot(snapshot)?.internal;
+    let (top, any) = (g.new_symbol()?, g.new_symbol()?);
+    g.new_rule(top, &[start])?;
+    g.new_rule(top, &[any])?;
+    g.set_start_symbol(top)?;
+    // a grammar with a cycle fails after it's been analyzed
+    let _ = g.precompute();
+    if g.is_precomputed()? {
+        let rules = 0..snapshot.rules.len() as Rule;
+        let mut lhs: Vec<_> = snapshot.rules.iter().map(|r| r.lhs).collect();
+        lhs.sort();
+        lhs.dedup();
+        for sym in lhs {
+            if !g.symbol_is_accessible(sym)? {
+                lints.push(Lint::InaccessibleSymbol(sym));
+            }
+        }
+        for rule in rules.clone() {
+            if !g.rule_is_productive(rule)? {
+                lints.push(Lint::UnproductiveRule(rule));
+            }
+        }
+        if g.has_cycle()? {
+            let mut cycle = vec![];
+            for rule in rules {
+                if g.rule_is_loop(rule)? {
+                    cycle.push(rule);
+                }
+            }
+            lints.push(Lint::Cycle(cycle));
+        }
+    }
+    for event in g.events()? {
+        match event {
+            Event::NullingTerminal(sym) => lints.push(Lint::NullingTerminal(sym)),
+            Event::CountedNullable(sym) => lints.push(Lint::CountedNullable(sym)),
+            _ => {}
+        }
+    }
+
+    if let Err(err) = precomputed {
+        lints.push(Lint::Precompute(err.to_string()));
+    }
+    Ok(Report { lints, names })
+}
+
+// Rules with the same LHS and RHS as an earlier rule.
+fn duplicates(rules: &[RuleInfo]) -> Vec<Lint> {
+    let mut lints = vec![];
+    let mut seen: HashMap<(Symbol, &[Symbol]), Rule> = HashMap::new();
+    for (id, rule) in rules.iter().enumerate() {
+        match seen.get(&(rule.lhs, &rule.rhs[..])) {
+            Some(&first) => lints.push(Lint::DuplicateRule(first, id as Rule)),
+            None => {
+                seen.insert((rule.lhs, &rule.rhs[..]), id as Rule);
+            }
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{Grammar, Item};
+
+    #[test]
+    fn clean() {
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "a").unwrap();
+        let list = g.star(None, a).unwrap();
+        g.set_start(list).unwrap();
+        let report = g.lint().unwrap();
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[test]
+    fn lints() {
+        let mut g = Grammar::new().unwrap();
+        assert_eq!(g.lint().unwrap().lints, vec![Lint::NoStart]);
+
+        let a = g.literal_string(None, "a").unwrap();
+        let a2 = g.literal_string(None, "a").unwrap();
+        let empty = g.rule(None, &[]).unwrap();
+        let x = g.new_symbol().unwrap();
+        let y = g.new_symbol().unwrap();
+        let xy = g.rule(Some(x), &[y, empty]).unwrap();
+        let yx = g.rule(Some(y), &[x]).unwrap();
+        g.rule(Some(y), &[a]).unwrap();
+        let stuck = g.new_symbol().unwrap();
+        let stuck_rule = g.rule(Some(stuck), &[stuck, a]).unwrap();
+        let start = g.rule(None, &[x, a2, stuck]).unwrap();
+        let unused = g.rule(None, &[a]).unwrap();
+        g.set_start(start).unwrap();
+        g.name(x, "x");
+        g.name(stuck_rule, "stuck");
+
+        let report = g.lint().unwrap();
+        assert_eq!(report.lints.len(), 5, "{}", report);
+        assert_eq!(
+            report.lints[..4],
+            [
+                Lint::InaccessibleSymbol(g.symbol(unused)),
+                Lint::UnproductiveRule(stuck_rule.rule()),
+                Lint::UnproductiveRule(start.rule()),
+                Lint::Cycle(vec![xy.rule(), yx.rule()]),
+            ]
+        );
+        match report.lints[4] {
+            Lint::Precompute(ref err) => assert!(err.to_lowercase().contains("unproductive start"), "{}", err),
+            ref lint => panic!("expected a precompute error, got {:?}", lint),
+        }
+        let text = report.to_string();
+        assert!(text.contains(&format!("cycle through rule {}, rule {}\n", xy.rule(), yx.rule())));
+        assert!(text.contains(&format!("rule {} (stuck) is unproductive\n", stuck_rule.rule())));
+
+        // linting doesn't precompute the grammar itself
+        assert!(!g.internal.is_precomputed().unwrap());
+    }
+
+    #[test]
+    fn counted_nullable() {
+        let mut g = Grammar::new().unwrap();
+        let empty = g.rule(None, &[]).unwrap();
+        let nulls = g.sequence(None, empty, Item::Symbol(-1), false, false).unwrap();
+        g.set_start(nulls).unwrap();
+
+        let lints = g.lint().unwrap().lints;
+        assert_eq!(lints[0], Lint::CountedNullable(g.symbol(empty)));
+        assert!(matches!(lints[1..], [Lint::Precompute(_)]), "{:?}", lints);
+    }
+
+    #[test]
+    fn duplicate_rules() {
+        let text = "marpa-grammar 1\nstart 1\nsymbol 0\nsymbol 1\nrule 0 1 -> 0\nrule 1 1 -> 0 \"again\"\n";
+        let report = text.parse::<Snapshot>().unwrap().lint().unwrap();
+        assert_eq!(report.lints, vec![Lint::DuplicateRule(0, 1)]);
+        assert_eq!(report.to_string(), "rule 1 (again) duplicates rule 0\n");
+    }
+}
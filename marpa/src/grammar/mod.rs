@@ -1,10 +1,30 @@
 use crate::result::Result;
 use crate::thin;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+mod analysis;
+mod fragments;
+mod lint;
+mod module;
+mod names;
+mod snapshot;
+
+pub use self::analysis::GrammarAnalysis;
+pub use self::lint::{Lint, Report};
+pub use self::module::{Import, Imported, Module};
+pub use self::names::Names;
+pub use self::snapshot::{RuleInfo, SequenceInfo, Snapshot, SymbolInfo};
+
+#[cfg(feature = "derive")]
+pub use marpa_derive::marpa_grammar;
 
 pub struct Grammar {
     internal: thin::Grammar,
     rules: HashMap<thin::Rule, thin::Symbol>,
+    // Symbols explicitly marked as terminal. libmarpa can't tell these apart
+    // from the terminals it finds during precomputation.
+    terminals: HashSet<thin::Symbol>,
+    names: Names,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -32,8 +52,10 @@ impl Item {
 impl Default for Grammar {
     fn default() -> Self {
         Grammar {
-            internal: thin::Grammar::new().unwrap(),
+            internal: thin::Grammar::unforced(thin::Config::new()).unwrap(),
             rules: Default::default(),
+            terminals: Default::default(),
+            names: Default::default(),
         }
     }
 }
@@ -41,26 +63,196 @@ impl Default for Grammar {
 impl Grammar {
     pub fn new() -> Result<Self> {
         let mut g = Grammar {
-            internal: thin::Grammar::new()?,
-            ..Default::default()
+            internal: thin::Grammar::unforced(thin::Config::new())?,
+            rules: Default::default(),
+            terminals: Default::default(),
+            names: Default::default(),
         };
 
         for _ in 0..256 {
-            g.internal.new_symbol()?;
+            g.valued_symbol()?;
+        }
+
+        Ok(g)
+    }
+
+    // Symbols are valued unless marked otherwise with `unvalued`.
+    fn valued_symbol(&mut self) -> Result<thin::Symbol> {
+        let sym = self.internal.new_symbol()?;
+        self.internal.symbol_is_valued_set(sym, true)?;
+        Ok(sym)
+    }
+
+    /// Name a rule or symbol. Naming a rule also names its LHS, unless the
+    /// LHS already has a name of its own.
+    pub fn name<S: Into<String>>(&mut self, item: Item, name: S) {
+        let name = name.into();
+        if let Item::Rule(rule) = item {
+            let lhs = self.symbol(item);
+            if self.names.symbol(lhs).is_none() {
+                self.names.set_symbol(lhs, name.clone());
+            }
+            self.names.set_rule(rule, name);
+        } else {
+            self.names.set_symbol(item.symbol(), name);
+        }
+    }
+
+    /// The names given with `name`. Clone them before unwrapping the
+    /// grammar to use them with the resulting trees.
+    pub fn names(&self) -> &Names {
+        &self.names
+    }
+
+    /// Skip the steps for a symbol, or for a rule's LHS, during evaluation,
+    /// leaving its value out of the children passed to
+    /// `Processor::proc_rule`. A rule's valued status is that of its LHS, so
+    /// this applies to every rule with the same LHS.
+    ///
+    /// The byte terminals are shared by every rule matching input, so they
+    /// can't be unvalued. Unvalue a rule built from them instead, such as
+    /// the one made by `literal_string`, which has an LHS of its own.
+    pub fn unvalued(&mut self, item: Item) -> Result<()> {
+        let sym = self.symbol(item);
+        if (0..256).contains(&sym) {
+            return Err(format!("byte terminal {} is shared, and can't be unvalued", sym).into());
+        }
+        self.internal.symbol_is_valued_set(sym, false)?;
+        Ok(())
+    }
+
+    /// Record everything needed to rebuild this grammar with `from_snapshot`.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let g = &self.internal;
+        let mut snapshot = Snapshot {
+            start: g.get_start_symbol().ok(),
+            ..Default::default()
+        };
+        for sym in g.symbols()? {
+            snapshot.symbols.push(SymbolInfo {
+                terminal: self.terminals.contains(&sym),
+                rank: g.symbol_rank_get(sym)?,
+                valued: g.symbol_is_valued(sym)?,
+                completion_event: g.symbol_is_completion_event(sym)?,
+                nulled_event: g.symbol_is_nulled_event(sym)?,
+                prediction_event: g.symbol_is_prediction_event(sym)?,
+                name: self.names.symbol(sym).map(String::from),
+            });
+        }
+        for rule in g.rules()? {
+            let sequence = if g.rule_is_sequence(rule)? {
+                Some(SequenceInfo {
+                    separator: g.sequence_separator(rule).ok(),
+                    min: g.sequence_min(rule)?,
+                    proper: g.rule_is_proper_separation(rule)?,
+                })
+            } else {
+                None
+            };
+            snapshot.rules.push(RuleInfo {
+                lhs: g.rule_lhs(rule)?,
+                rhs: g.rule_rhs(rule)?,
+                sequence,
+                rank: g.rule_rank_get(rule)?,
+                null_high: g.rule_null_high(rule)?,
+                name: self.names.rule(rule).map(String::from),
+            });
+        }
+        Ok(snapshot)
+    }
+
+    /// Rebuild a grammar from a `Snapshot`. Symbols and rules get the same
+    /// ids as in the original grammar.
+    pub fn from_snapshot(snapshot: &Snapshot) -> Result<Self> {
+        let mut g = Grammar::default();
+
+        for (id, info) in snapshot.symbols.iter().enumerate() {
+            let sym = g.copy_symbol(info, info.name.clone())?;
+            if sym != id as thin::Symbol {
+                return Err(format!("snapshot: symbol {} was created as {}", id, sym).into());
+            }
+        }
+
+        for (id, info) in snapshot.rules.iter().enumerate() {
+            let rule = g.copy_rule(info, |sym| sym, info.name.clone())?;
+            if rule != id as thin::Rule {
+                return Err(format!("snapshot: rule {} was created as {}", id, rule).into());
+            }
         }
 
+        if let Some(start) = snapshot.start {
+            g.internal.set_start_symbol(start)?;
+        }
         Ok(g)
     }
 
+    // Creates a symbol with the same flags as `info`.
+    fn copy_symbol(&mut self, info: &SymbolInfo, name: Option<String>) -> Result<thin::Symbol> {
+        let sym = self.valued_symbol()?;
+        self.internal.symbol_is_valued_set(sym, info.valued)?;
+        if info.terminal {
+            self.internal.symbol_set_terminal(sym, true)?;
+            self.terminals.insert(sym);
+        }
+        self.internal.symbol_rank_set(sym, info.rank)?;
+        self.internal.symbol_is_completion_event_set(sym, info.completion_event)?;
+        self.internal.symbol_is_nulled_event_set(sym, info.nulled_event)?;
+        self.internal.symbol_is_prediction_event_set(sym, info.prediction_event)?;
+        if let Some(name) = name {
+            self.names.set_symbol(sym, name);
+        }
+        Ok(sym)
+    }
+
+    // Creates a rule like `info`, with its symbols mapped through `map`.
+    fn copy_rule<F>(&mut self, info: &RuleInfo, map: F, name: Option<String>) -> Result<thin::Rule>
+    where
+        F: Fn(thin::Symbol) -> thin::Symbol,
+    {
+        let lhs = map(info.lhs);
+        let rhs: Vec<_> = info.rhs.iter().map(|&sym| map(sym)).collect();
+        let rule = match info.sequence {
+            Some(ref seq) => {
+                let item = rhs.first().cloned().unwrap_or(-1);
+                let sep = seq.separator.map_or(-1, &map);
+                self.internal.new_sequence(lhs, item, sep, seq.min > 0, seq.proper)?
+            }
+            None => self.internal.new_rule(lhs, &rhs)?,
+        };
+        self.internal.rule_rank_set(rule, info.rank)?;
+        self.internal.rule_null_high_set(rule, info.null_high)?;
+        self.add_rule(rule, lhs);
+        if let Some(name) = name {
+            self.names.set_rule(rule, name);
+        }
+        Ok(rule)
+    }
+
+    /// Compute nullable, FIRST and FOLLOW sets for the grammar as it is now.
+    pub fn analysis(&self) -> Result<GrammarAnalysis> {
+        Ok(GrammarAnalysis::new(&self.snapshot()?))
+    }
+
+    /// Check the grammar for problems. Copies of the grammar are precomputed,
+    /// so this one can still be changed afterwards.
+    pub fn lint(&self) -> Result<Report> {
+        lint::report(&self.snapshot()?, self.names.clone())
+    }
+
     fn get_lhs(&mut self, lhs: Option<Item>) -> Result<thin::Symbol> {
         match lhs {
             Some(it) => Ok(self.symbol(it)),
-            None => self.internal.new_symbol(),
+            None => self.valued_symbol(),
         }
     }
 
+    fn add_rule(&mut self, rule: thin::Rule, lhs: thin::Symbol) {
+        self.rules.insert(rule, lhs);
+        self.names.set_lhs(rule, lhs);
+    }
+
     pub fn new_symbol(&mut self) -> Result<Item> {
-        Ok(Item::Symbol(self.internal.new_symbol()?))
+        Ok(Item::Symbol(self.valued_symbol()?))
     }
 
     pub fn set_start(&mut self, it: Item) -> Result<Item> {
@@ -88,7 +280,7 @@ impl Grammar {
         let lhs = self.get_lhs(lhs)?;
         let rhs = self.symbols(rhs);
         let r = self.internal.new_rule(lhs, &rhs)?;
-        self.rules.insert(r, lhs);
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
@@ -96,51 +288,51 @@ impl Grammar {
         let lhs = self.get_lhs(lhs)?;
         let sep = self.symbol(sep);
         let rhs = self.symbol(rhs);
-        let r = self.internal.new_sequence(lhs, rhs, sep, proper, nonempty)?;
-        self.rules.insert(r, lhs);
+        let r = self.internal.new_sequence(lhs, rhs, sep, nonempty, proper)?;
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
     pub fn plus(&mut self, lhs: Option<Item>, rhs: Item) -> Result<Item> {
         let lhs = self.get_lhs(lhs)?;
-        let internal = self.internal.new_symbol()?;
-        let rec = self.internal.new_symbol()?;
+        let internal = self.valued_symbol()?;
+        let rec = self.valued_symbol()?;
         let rhs = self.symbol(rhs);
         self.internal.new_rule(internal, &[rhs])?;
         self.internal.new_rule(rec, &[internal])?;
         self.internal.new_rule(rec, &[rec, internal])?;
         let r = self.internal.new_rule(lhs, &[rec])?;
-        self.rules.insert(r, lhs);
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
     pub fn star(&mut self, lhs: Option<Item>, rhs: Item) -> Result<Item> {
         let lhs = self.get_lhs(lhs)?;
-        let internal = self.internal.new_symbol()?;
-        let rec = self.internal.new_symbol()?;
+        let internal = self.valued_symbol()?;
+        let rec = self.valued_symbol()?;
         let rhs = self.symbol(rhs);
         self.internal.new_rule(internal, &[rhs])?;
         self.internal.new_rule(rec, &[])?;
         self.internal.new_rule(rec, &[rec, internal])?;
         let r = self.internal.new_rule(lhs, &[rec])?;
-        self.rules.insert(r, lhs);
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
     pub fn maybe(&mut self, lhs: Option<Item>, rhs: Item) -> Result<Item> {
         let lhs = self.get_lhs(lhs)?;
         let rhs = self.symbol(rhs);
-        let internal = self.internal.new_symbol()?;
+        let internal = self.valued_symbol()?;
         self.internal.new_rule(internal, &[])?;
         self.internal.new_rule(internal, &[rhs])?;
         let r = self.internal.new_rule(lhs, &[internal])?;
-        self.rules.insert(r, lhs);
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
     pub fn alternative(&mut self, lhs: Option<Item>, rhs: &[Item]) -> Result<Item> {
         let lhs = self.get_lhs(lhs)?;
-        let internal = self.internal.new_symbol()?;
+        let internal = self.valued_symbol()?;
 
         for it in rhs.iter() {
             let it = self.symbol(*it);
@@ -148,7 +340,7 @@ impl Grammar {
         }
 
         let r = self.internal.new_rule(lhs, &[internal])?;
-        self.rules.insert(r, lhs);
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
@@ -173,7 +365,7 @@ impl Grammar {
         }
 
         let lhs = self.get_lhs(lhs)?;
-        let internal = self.internal.new_symbol()?;
+        let internal = self.valued_symbol()?;
 
         for b in (::std::ops::Range::<u16> { start: 0, end: 256 }) {
             if !set.contains(&(b as u8)) {
@@ -182,7 +374,7 @@ impl Grammar {
         }
 
         let r = self.internal.new_rule(lhs, &[internal])?;
-        self.rules.insert(r, lhs);
+        self.add_rule(r, lhs);
         Ok(Item::Rule(r))
     }
 
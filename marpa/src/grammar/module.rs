@@ -0,0 +1,242 @@
+use crate::grammar::{Grammar, Item, Snapshot};
+use crate::result::Result;
+use crate::thin::{Rule, Symbol};
+use std::collections::{HashMap, HashSet};
+
+/// A grammar to be imported into others, along with the symbols it exports.
+#[derive(Clone, Debug)]
+pub struct Module {
+    snapshot: Snapshot,
+    exports: HashMap<String, Symbol>,
+}
+
+impl Module {
+    /// Capture the rules of `grammar` as they are now. Its start symbol
+    /// isn't used.
+    pub fn new(grammar: &Grammar) -> Result<Module> {
+        Ok(Module {
+            snapshot: grammar.snapshot()?,
+            exports: HashMap::new(),
+        })
+    }
+
+    /// Make a symbol of the module's grammar, or a rule's LHS, available to
+    /// importers as `name`.
+    pub fn export<S: Into<String>>(&mut self, name: S, item: Item) -> Result<()> {
+        let sym = match item {
+            Item::Symbol(sym) => sym,
+            Item::Rule(rule) => match self.snapshot.rules.get(rule as usize) {
+                Some(info) => info.lhs,
+                None => return Err(format!("module: no rule {}", rule).into()),
+            },
+        };
+        self.exports.insert(name.into(), sym);
+        Ok(())
+    }
+
+    fn symbol_named(&self, name: &str) -> Result<Symbol> {
+        match self.snapshot.symbols.iter().position(|s| s.name.as_deref() == Some(name)) {
+            Some(sym) => Ok(sym as Symbol),
+            None => Err(format!("module: no symbol named `{}`", name).into()),
+        }
+    }
+}
+
+/// How to import a `Module` with `Grammar::import`.
+///
+/// The symbols of the module get fresh symbols in the importing grammar,
+/// named `namespace::name`, unless they're bound to existing ones. Byte
+/// symbols are shared.
+#[derive(Clone, Debug)]
+pub struct Import<'m> {
+    module: &'m Module,
+    namespace: String,
+    bindings: HashMap<Symbol, Item>,
+    replaced: HashSet<Symbol>,
+    omitted: HashSet<Rule>,
+}
+
+impl<'m> Import<'m> {
+    pub fn new<S: Into<String>>(module: &'m Module, namespace: S) -> Import<'m> {
+        Import {
+            module,
+            namespace: namespace.into(),
+            bindings: HashMap::new(),
+            replaced: HashSet::new(),
+            omitted: HashSet::new(),
+        }
+    }
+
+    /// Use `item` from the importing grammar wherever the module uses the
+    /// symbol called `name`. The module's rules for the symbol are kept, as
+    /// alternatives for `item`.
+    pub fn bind(&mut self, name: &str, item: Item) -> Result<()> {
+        let sym = self.module.symbol_named(name)?;
+        self.bindings.insert(sym, item);
+        Ok(())
+    }
+
+    /// Like `bind`, but leave out the module's rules for the symbol, so that
+    /// only the importing grammar's rules for `item` apply.
+    pub fn replace(&mut self, name: &str, item: Item) -> Result<()> {
+        let sym = self.module.symbol_named(name)?;
+        self.bindings.insert(sym, item);
+        self.replaced.insert(sym);
+        Ok(())
+    }
+
+    /// Leave out the module's rules called `name`, to be overridden with
+    /// rules of the importing grammar.
+    pub fn omit(&mut self, name: &str) -> Result<()> {
+        let rules = &self.module.snapshot.rules;
+        let before = self.omitted.len();
+        for (id, rule) in rules.iter().enumerate() {
+            if rule.name.as_deref() == Some(name) {
+                self.omitted.insert(id as Rule);
+            }
+        }
+        if self.omitted.len() == before {
+            return Err(format!("module: no rule named `{}`", name).into());
+        }
+        Ok(())
+    }
+
+    fn qualify(&self, name: &str) -> String {
+        if self.namespace.is_empty() {
+            name.into()
+        } else {
+            format!("{}::{}", self.namespace, name)
+        }
+    }
+}
+
+/// The items an `Import` added to a grammar.
+#[derive(Clone, Debug)]
+pub struct Imported {
+    exports: HashMap<String, Item>,
+    rules: HashMap<Rule, Item>,
+}
+
+impl Imported {
+    /// The symbol the module exported as `name`.
+    pub fn get(&self, name: &str) -> Option<Item> {
+        self.exports.get(name).cloned()
+    }
+
+    /// The copy of a rule of the module's grammar, if it wasn't left out.
+    pub fn rule(&self, rule: Item) -> Option<Item> {
+        self.rules.get(&rule.rule()).cloned()
+    }
+}
+
+impl Grammar {
+    /// Copy the rules of a module into this grammar.
+    pub fn import(&mut self, import: &Import) -> Result<Imported> {
+        let snapshot = &import.module.snapshot;
+
+        let mut symbols = Vec::with_capacity(snapshot.symbols.len());
+        for (id, info) in snapshot.symbols.iter().enumerate() {
+            let id = id as Symbol;
+            let sym = match import.bindings.get(&id) {
+                Some(&item) => self.symbol(item),
+                None if id < 256 => id,
+                None => self.copy_symbol(info, info.name.as_ref().map(|n| import.qualify(n)))?,
+            };
+            symbols.push(sym);
+        }
+
+        let mut rules = HashMap::new();
+        for (id, info) in snapshot.rules.iter().enumerate() {
+            if import.replaced.contains(&info.lhs) || import.omitted.contains(&(id as Rule)) {
+                continue;
+            }
+            let name = info.name.as_ref().map(|n| import.qualify(n));
+            let rule = self.copy_rule(info, |sym| symbols[sym as usize], name)?;
+            rules.insert(id as Rule, Item::Rule(rule));
+        }
+
+        let exports = import
+            .module
+            .exports
+            .iter()
+            .map(|(name, &sym)| (name.clone(), Item::Symbol(symbols[sym as usize])))
+            .collect();
+        Ok(Imported { exports, rules })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::byte_scanner::ByteScanner;
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    // expr ::= atom | expr '+' atom
+    // atom ::= [0-9]+ | ident
+    fn expr_module() -> (Module, Item) {
+        let mut g = Grammar::new().unwrap();
+        let expr = g.new_symbol().unwrap();
+        let atom = g.new_symbol().unwrap();
+        let ident = g.new_symbol().unwrap();
+        let plus = g.literal_string(None, "+").unwrap();
+        let digit = g.byte_range(None, b'0', b'9').unwrap();
+        g.rule(Some(expr), &[atom]).unwrap();
+        let add = g.rule(Some(expr), &[expr, plus, atom]).unwrap();
+        g.plus(Some(atom), digit).unwrap();
+        g.rule(Some(atom), &[ident]).unwrap();
+        g.name(expr, "expr");
+        g.name(atom, "atom");
+        g.name(ident, "ident");
+        g.name(add, "add");
+
+        let mut module = Module::new(&g).unwrap();
+        module.export("expr", expr).unwrap();
+        (module, add)
+    }
+
+    fn parses(g: Grammar, input: &str) -> bool {
+        let mut p = Parser::with_grammar(g.unwrap());
+        p.run_recognizer(ByteScanner::new(Cursor::new(input))).is_ok()
+    }
+
+    // stmt ::= "print " expr, with expr imported from the module as `e`
+    fn host(module: &Module, setup: impl FnOnce(&mut Import, &mut Grammar, Item)) -> Grammar {
+        let mut g = Grammar::new().unwrap();
+        let ident = g.byte_range(None, b'a', b'z').unwrap();
+        let mut import = Import::new(module, "e");
+        setup(&mut import, &mut g, ident);
+        let imported = g.import(&import).unwrap();
+        let print = g.literal_string(None, "print ").unwrap();
+        let stmt = g.rule(None, &[print, imported.get("expr").unwrap()]).unwrap();
+        g.set_start(stmt).unwrap();
+        g
+    }
+
+    #[test]
+    fn import() {
+        let (module, add) = expr_module();
+
+        let g = host(&module, |import, _, ident| import.bind("ident", ident).unwrap());
+        assert!(g.names().symbols_named("e::expr").next().is_some());
+        assert!(g.names().rules_named("e::add").next().is_some());
+        assert!(parses(g, "print 1+x+23"));
+
+        let g = host(&module, |import, g, ident| {
+            let letter = g.rule(None, &[ident]).unwrap();
+            import.replace("atom", letter).unwrap();
+        });
+        assert!(!parses(g, "print 1+x"));
+
+        let g = host(&module, |import, _, ident| {
+            import.bind("ident", ident).unwrap();
+            import.omit("add").unwrap();
+        });
+        assert!(g.names().rules_named("e::add").next().is_none());
+        assert!(!parses(g, "print 1+x"));
+
+        let mut import = Import::new(&module, "");
+        assert!(import.bind("nope", add).is_err());
+        assert!(import.omit("nope").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use crate::thin::{Rule, Symbol};
+use std::collections::HashMap;
+
+/// Human-readable names for the rules and symbols of a grammar.
+#[derive(Clone, Debug, Default)]
+pub struct Names {
+    rules: HashMap<Rule, String>,
+    symbols: HashMap<Symbol, String>,
+    lhs: HashMap<Rule, Symbol>,
+}
+
+impl Names {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set_rule<S: Into<String>>(&mut self, rule: Rule, name: S) {
+        self.rules.insert(rule, name.into());
+    }
+
+    pub fn set_symbol<S: Into<String>>(&mut self, sym: Symbol, name: S) {
+        self.symbols.insert(sym, name.into());
+    }
+
+    pub fn set_lhs(&mut self, rule: Rule, lhs: Symbol) {
+        self.lhs.insert(rule, lhs);
+    }
+
+    pub fn rule(&self, rule: Rule) -> Option<&str> {
+        self.rules.get(&rule).map(String::as_str)
+    }
+
+    pub fn symbol(&self, sym: Symbol) -> Option<&str> {
+        self.symbols.get(&sym).map(String::as_str)
+    }
+
+    pub fn lhs(&self, rule: Rule) -> Option<Symbol> {
+        self.lhs.get(&rule).cloned()
+    }
+
+    /// The rules with the given name, along with the rules whose LHS has it.
+    pub fn rules_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Rule> + 'a {
+        self.lhs
+            .iter()
+            .filter(move |&(rule, &lhs)| self.rule(*rule) == Some(name) || self.symbol(lhs) == Some(name))
+            .map(|(&rule, _)| rule)
+    }
+
+    pub fn symbols_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Symbol> + 'a {
+        self.symbols.iter().filter(move |&(_, n)| n == name).map(|(&sym, _)| sym)
+    }
+}
@@ -0,0 +1,295 @@
+use crate::error::Error;
+use crate::result::Result;
+use crate::thin::Symbol;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const HEADER: &str = "marpa-grammar 1";
+
+/// Everything needed to rebuild a `Grammar`: its symbols, rules, names and
+/// start symbol, in the order they were created.
+///
+/// The text form, from `Display` and `FromStr`, is stable, so it can be
+/// written to disk and compared with `hash` to tell when a grammar changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    pub start: Option<Symbol>,
+    pub symbols: Vec<SymbolInfo>,
+    pub rules: Vec<RuleInfo>,
+}
+
+/// Only symbols explicitly marked as terminal have `terminal` set, not the
+/// ones libmarpa finds to be terminals when precomputing.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SymbolInfo {
+    pub terminal: bool,
+    pub valued: bool,
+    pub completion_event: bool,
+    pub nulled_event: bool,
+    pub prediction_event: bool,
+    pub rank: i32,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RuleInfo {
+    pub lhs: Symbol,
+    pub rhs: Vec<Symbol>,
+    pub sequence: Option<SequenceInfo>,
+    pub rank: i32,
+    pub null_high: bool,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SequenceInfo {
+    pub separator: Option<Symbol>,
+    pub min: i32,
+    pub proper: bool,
+}
+
+impl Snapshot {
+    /// A 64-bit FNV-1a hash of the text form, which is the same across
+    /// platforms and builds.
+    pub fn hash(&self) -> u64 {
+        self.to_string()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        if let Some(start) = self.start {
+            writeln!(f, "start {}", start)?;
+        }
+        for (id, sym) in self.symbols.iter().enumerate() {
+            write!(f, "symbol {}", id)?;
+            let flags = [
+                (sym.terminal, "terminal"),
+                (sym.valued, "valued"),
+                (sym.completion_event, "completion"),
+                (sym.nulled_event, "nulled"),
+                (sym.prediction_event, "prediction"),
+            ];
+            for &(_, flag) in flags.iter().filter(|f| f.0) {
+                write!(f, " {}", flag)?;
+            }
+            if sym.rank != 0 {
+                write!(f, " rank={}", sym.rank)?;
+            }
+            if let Some(ref name) = sym.name {
+                write!(f, " {:?}", name)?;
+            }
+            writeln!(f)?;
+        }
+        for (id, rule) in self.rules.iter().enumerate() {
+            write!(f, "rule {} {} ->", id, rule.lhs)?;
+            for sym in &rule.rhs {
+                write!(f, " {}", sym)?;
+            }
+            if let Some(ref seq) = rule.sequence {
+                write!(f, " seq min={}", seq.min)?;
+                if let Some(sep) = seq.separator {
+                    write!(f, " sep={}", sep)?;
+                }
+                if seq.proper {
+                    write!(f, " proper")?;
+                }
+            }
+            if rule.rank != 0 {
+                write!(f, " rank={}", rule.rank)?;
+            }
+            if rule.null_high {
+                write!(f, " null_high")?;
+            }
+            if let Some(ref name) = rule.name {
+                write!(f, " {:?}", name)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Snapshot> {
+        let mut lines = s.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(Error::from(format!("snapshot: expected `{}`", HEADER))),
+        }
+
+        let mut snapshot = Snapshot::default();
+        for (n, line) in lines {
+            let bad = || Error::from(format!("snapshot: invalid line {}: {}", n + 1, line));
+            // a number, keeping the error from parsing it
+            let int = |word: &str| -> Result<i32> {
+                word.parse()
+                    .map_err(|err| Error::wrap(format!("snapshot: invalid line {}: {}", n + 1, line), err))
+            };
+            let (line, name) = match line.find('"') {
+                Some(i) => (&line[..i], Some(unescape(&line[i..]).ok_or_else(bad)?)),
+                None => (line, None),
+            };
+            let mut words = line.split_whitespace();
+            let kind = words.next();
+            if kind.is_none() {
+                continue;
+            }
+            let words: Vec<_> = words.collect();
+            match kind {
+                Some("start") if words.len() == 1 => snapshot.start = Some(int(words[0])?),
+                Some("symbol") => {
+                    if words.first().cloned() != Some(&*snapshot.symbols.len().to_string()) {
+                        return Err(bad());
+                    }
+                    let mut sym = SymbolInfo { name, ..Default::default() };
+                    for flag in &words[1..] {
+                        match *flag {
+                            "terminal" => sym.terminal = true,
+                            "valued" => sym.valued = true,
+                            "completion" => sym.completion_event = true,
+                            "nulled" => sym.nulled_event = true,
+                            "prediction" => sym.prediction_event = true,
+                            flag => match flag.strip_prefix("rank=") {
+                                Some(rank) => sym.rank = int(rank)?,
+                                None => return Err(bad()),
+                            },
+                        }
+                    }
+                    snapshot.symbols.push(sym);
+                }
+                Some("rule") => {
+                    if words.len() < 3 || words[0] != snapshot.rules.len().to_string() || words[2] != "->" {
+                        return Err(bad());
+                    }
+                    let mut rule = RuleInfo {
+                        lhs: int(words[1])?,
+                        name,
+                        ..Default::default()
+                    };
+                    for word in &words[3..] {
+                        let (key, value) = match word.find('=') {
+                            Some(i) => (&word[..i], Some(&word[i + 1..])),
+                            None => (*word, None),
+                        };
+                        let num = || value.ok_or_else(bad).and_then(int);
+                        match (key, rule.sequence.as_mut()) {
+                            ("seq", None) => rule.sequence = Some(SequenceInfo::default()),
+                            ("min", Some(seq)) => seq.min = num()?,
+                            ("sep", Some(seq)) => seq.separator = Some(num()?),
+                            ("proper", Some(seq)) => seq.proper = true,
+                            ("rank", _) => rule.rank = num()?,
+                            ("null_high", _) => rule.null_high = true,
+                            (sym, _) if value.is_none() => rule.rhs.push(int(sym)?),
+                            _ => return Err(bad()),
+                        }
+                    }
+                    snapshot.rules.push(rule);
+                }
+                _ => return Err(bad()),
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+// Reverses the `{:?}` formatting of a string.
+fn unescape(quoted: &str) -> Option<String> {
+    let mut chars = quoted.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                ::std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+            }
+            c => c,
+        });
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::lexer::byte_scanner::ByteScanner;
+    use crate::parser::Parser;
+    use std::error::Error as _;
+    use std::io::Cursor;
+    use std::num::ParseIntError;
+
+    fn grammar(rank: i32) -> Grammar {
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "a").unwrap();
+        let comma = g.literal_string(None, ",").unwrap();
+        let list = g.sequence(None, a, comma, true, true).unwrap();
+        let start = g.rule(None, &[list]).unwrap();
+        g.internal.rule_rank_set(start.rule(), rank).unwrap();
+        g.internal.symbol_rank_set(g.symbol(a), -1).unwrap();
+        g.name(a, "a \"quoted\"\n");
+        g.name(list, "list");
+        g.unvalued(comma).unwrap();
+        g.set_start(start).unwrap();
+        g
+    }
+
+    #[test]
+    fn round_trip() {
+        let g = grammar(2);
+        let snapshot = g.snapshot().unwrap();
+        let text = snapshot.to_string();
+        assert!(text.contains("\nrule 2 258 -> 256 seq min=1 sep=257 proper \"list\"\n"));
+        assert!(text.contains("\nrule 3 259 -> 258 rank=2\n"));
+        assert!(text.contains("\nsymbol 256 valued rank=-1 \"a \\\"quoted\\\"\\n\"\n"));
+        assert!(!text.contains("terminal"));
+
+        let parsed: Snapshot = text.parse().unwrap();
+        assert_eq!(parsed, snapshot);
+        assert_eq!(parsed.hash(), snapshot.hash());
+        assert_ne!(grammar(1).snapshot().unwrap().hash(), snapshot.hash());
+
+        let restored = Grammar::from_snapshot(&parsed).unwrap();
+        assert_eq!(restored.snapshot().unwrap(), snapshot);
+        assert_eq!(restored.names().rule(0), Some("a \"quoted\"\n"));
+
+        let mut p = Parser::with_grammar(restored.unwrap());
+        assert!(p.run_recognizer(ByteScanner::new(Cursor::new("a,a"))).is_ok());
+
+        assert!("marpa-grammar 1\nsymbol 1".parse::<Snapshot>().is_err());
+        let err = "marpa-grammar 1\nrule 0 1 -> x".parse::<Snapshot>().unwrap_err();
+        assert!(err.source().unwrap().is::<ParseIntError>());
+    }
+
+    #[test]
+    fn explicit_terminals() {
+        let text = "marpa-grammar 1\nstart 2\nsymbol 0 terminal valued\nsymbol 1 valued\nsymbol 2\nrule 0 2 -> 0 1\n";
+        let g = Grammar::from_snapshot(&text.parse().unwrap()).unwrap();
+        assert_eq!(g.snapshot().unwrap().to_string(), text);
+
+        // A precomputed copy finds symbol 1 to be a terminal too
+        let mut copy = Grammar::from_snapshot(&g.snapshot().unwrap()).unwrap().unwrap();
+        copy.precompute().unwrap();
+        assert!(copy.symbol_is_terminal(1).unwrap());
+        assert_eq!(g.snapshot().unwrap().to_string(), text);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::error::Error;
+use crate::result::Result;
+use regex::bytes::Regex;
+
+/// Patterns of input to skip between tokens, like whitespace and comments.
+///
+/// Discards are given to a `ScanlessGrammar` with `discard_patterns`, and
+/// tried wherever a lexeme could start. The longest match is skipped, unless
+/// a lexeme there is at least as long, so grammars don't need to mention
+/// discards at all. Discards only ever come between lexemes, so `a b` is two
+/// lexemes, never the identifier `ab`.
+#[derive(Clone, Debug, Default)]
+pub struct Discard {
+    patterns: Vec<Regex>,
+}
+
+impl Discard {
+    pub fn new() -> Discard {
+        Discard::default()
+    }
+
+    /// Skip input matching the regular expression `pattern`.
+    pub fn pattern(&mut self, pattern: &str) -> Result<()> {
+        match Regex::new(&format!(r"\A(?:{})", pattern)) {
+            Ok(re) => {
+                self.patterns.push(re);
+                Ok(())
+            }
+            Err(err) => Err(Error::wrap("invalid discard pattern", err)),
+        }
+    }
+
+    /// Skip ASCII whitespace.
+    pub fn whitespace(&mut self) {
+        self.pattern(r"[\t\n\r ]+").unwrap()
+    }
+
+    /// Skip comments from `start` to the end of the line.
+    pub fn line_comment(&mut self, start: &str) {
+        self.pattern(&format!(r"{}[^\n]*", regex::escape(start))).unwrap()
+    }
+
+    /// Skip comments from `open` to the first `close` after it.
+    pub fn block_comment(&mut self, open: &str, close: &str) {
+        self.pattern(&format!(r"(?s){}.*?{}", regex::escape(open), regex::escape(close))).unwrap()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// The length of the longest match at the start of `input`, if any is
+    /// longer than zero.
+    pub fn longest_match(&self, input: &[u8]) -> Option<usize> {
+        self.patterns
+            .iter()
+            .filter_map(|re| re.find(input))
+            .map(|m| m.end())
+            .filter(|&len| len > 0)
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Discard;
+
+    #[test]
+    fn longest_match() {
+        let mut d = Discard::new();
+        d.whitespace();
+        d.line_comment("//");
+        d.block_comment("/*", "*/");
+
+        assert_eq!(d.longest_match(b" \n\tx"), Some(3));
+        assert_eq!(d.longest_match(b"// a\nb"), Some(4));
+        assert_eq!(d.longest_match(b"/* a\n*/ */"), Some(7));
+        assert_eq!(d.longest_match(b"x "), None);
+        assert_eq!(d.longest_match(b"/ /"), None);
+        assert!(d.pattern("(").is_err());
+    }
+}
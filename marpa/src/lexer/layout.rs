@@ -0,0 +1,319 @@
+use crate::error::{Error, Position};
+use crate::lexer::token::Token;
+use crate::result::Result;
+use crate::thin::Symbol;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// A token from a `Layout`: either one of its source's, or a layout token.
+///
+/// Layout tokens have negative values, so that they can be told apart from
+/// the source's tokens, whose values must not be negative.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayoutToken<T> {
+    Token(T),
+    Indent(Symbol),
+    Dedent(Symbol),
+    Newline(Symbol),
+}
+
+impl<T: Token> From<(Symbol, i32)> for LayoutToken<T> {
+    fn from((sym, val): (Symbol, i32)) -> Self {
+        match val {
+            -1 => LayoutToken::Indent(sym),
+            -2 => LayoutToken::Dedent(sym),
+            -3 => LayoutToken::Newline(sym),
+            _ => LayoutToken::Token((sym, val).into()),
+        }
+    }
+}
+
+impl<T: Token> Token for LayoutToken<T> {
+    fn sym(&self) -> Symbol {
+        match *self {
+            LayoutToken::Token(ref tok) => tok.sym(),
+            LayoutToken::Indent(sym) | LayoutToken::Dedent(sym) | LayoutToken::Newline(sym) => sym,
+        }
+    }
+
+    fn value(&self) -> i32 {
+        match *self {
+            LayoutToken::Token(ref tok) => tok.value(),
+            LayoutToken::Indent(_) => -1,
+            LayoutToken::Dedent(_) => -2,
+            LayoutToken::Newline(_) => -3,
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for LayoutToken<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LayoutToken::Token(ref tok) => tok.fmt(f),
+            LayoutToken::Indent(_) => write!(f, "INDENT"),
+            LayoutToken::Dedent(_) => write!(f, "DEDENT"),
+            LayoutToken::Newline(_) => write!(f, "NEWLINE"),
+        }
+    }
+}
+
+/// Turns the indentation of byte tokens into layout tokens, for grammars of
+/// Python-like languages.
+///
+/// The whitespace at the start of each line is dropped, and an INDENT comes
+/// before a line indented further than the one before it. Lines indented
+/// less get a DEDENT for each level they close. The newline at the end of a
+/// line is replaced with a NEWLINE, and lines with nothing but whitespace are
+/// dropped altogether. Between brackets, lines are joined: newlines and
+/// indentation are left as they are, for the grammar to handle.
+///
+/// A line indented less than the one before it, but not as little as any
+/// outer line, is an error, as in Python. The tokens end there, and `check`
+/// returns the error, so check it after parsing from a `&mut Layout`.
+pub struct Layout<I, T> {
+    tokens: I,
+    indent: Symbol,
+    dedent: Symbol,
+    newline: Symbol,
+    open: Vec<Symbol>,
+    close: Vec<Symbol>,
+    tab_width: usize,
+    levels: Vec<usize>,
+    depth: usize,
+    line_start: bool,
+    done: bool,
+    queue: VecDeque<LayoutToken<T>>,
+    // the number of tokens read from `tokens`
+    read: usize,
+    error: Option<Error>,
+}
+
+impl<I, T> Layout<I, T>
+where
+    I: Iterator<Item = T>,
+    T: Token,
+{
+    /// Wrap `tokens`, using the given terminals of the grammar for layout.
+    pub fn new(tokens: I, indent: Symbol, dedent: Symbol, newline: Symbol) -> Self {
+        Layout {
+            tokens,
+            indent,
+            dedent,
+            newline,
+            open: bytes(b"([{"),
+            close: bytes(b")]}"),
+            tab_width: 8,
+            levels: vec![0],
+            depth: 0,
+            line_start: true,
+            done: false,
+            queue: VecDeque::new(),
+            read: 0,
+            error: None,
+        }
+    }
+
+    /// Fail if the tokens ended early at an inconsistent dedent.
+    pub fn check(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// The bytes that open and close brackets, `([{` and `)]}` by default.
+    pub fn brackets(&mut self, open: &[u8], close: &[u8]) {
+        self.open = bytes(open);
+        self.close = bytes(close);
+    }
+
+    /// Tabs indent to the next multiple of `width`, 8 by default.
+    pub fn tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+    }
+
+    // Reads the indentation of the next non-blank line, queueing its layout
+    // tokens and first token.
+    fn start_line(&mut self) {
+        let mut width = 0;
+        loop {
+            let tok = match self.next_token() {
+                Some(tok) => tok,
+                None => return self.finish(),
+            };
+            match tok.sym() {
+                32 => width += 1,
+                9 => width = (width / self.tab_width + 1) * self.tab_width,
+                13 => {}
+                10 => width = 0,
+                _ => {
+                    if self.indent_to(width) {
+                        self.line_start = false;
+                        self.token(tok);
+                    } else {
+                        self.queue.clear();
+                        self.done = true;
+                        self.error = Some(Error::Parse {
+                            location: Position::Token(self.read - 1),
+                            message: "dedent does not match any outer indentation level".into(),
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    // Queues the layout tokens for a line indented by `width`, returning
+    // false if it dedents to between two levels.
+    fn indent_to(&mut self, width: usize) -> bool {
+        let dedented = width < self.levels[self.levels.len() - 1];
+        while width < self.levels[self.levels.len() - 1] {
+            self.levels.pop();
+            self.queue.push_back(LayoutToken::Dedent(self.dedent));
+        }
+        if width > self.levels[self.levels.len() - 1] {
+            if dedented {
+                return false;
+            }
+            self.levels.push(width);
+            self.queue.push_back(LayoutToken::Indent(self.indent));
+        }
+        true
+    }
+
+    fn next_token(&mut self) -> Option<T> {
+        let tok = self.tokens.next();
+        self.read += tok.is_some() as usize;
+        tok
+    }
+
+    fn token(&mut self, tok: T) {
+        let sym = tok.sym();
+        if sym == 10 && self.depth == 0 {
+            self.line_start = true;
+            self.queue.push_back(LayoutToken::Newline(self.newline));
+            return;
+        }
+        if self.open.contains(&sym) {
+            self.depth += 1;
+        } else if self.close.contains(&sym) {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        self.queue.push_back(LayoutToken::Token(tok));
+    }
+
+    // Ends the last line, and closes every open level.
+    fn finish(&mut self) {
+        if !self.line_start {
+            self.queue.push_back(LayoutToken::Newline(self.newline));
+        }
+        self.indent_to(0);
+        self.done = true;
+    }
+}
+
+impl<I, T> Iterator for Layout<I, T>
+where
+    I: Iterator<Item = T>,
+    T: Token,
+{
+    type Item = LayoutToken<T>;
+
+    fn next(&mut self) -> Option<LayoutToken<T>> {
+        while self.queue.is_empty() && !self.done {
+            if self.line_start {
+                self.start_line();
+                continue;
+            }
+            match self.next_token() {
+                Some(tok) => self.token(tok),
+                None => self.finish(),
+            }
+        }
+        self.queue.pop_front()
+    }
+}
+
+fn bytes(bytes: &[u8]) -> Vec<Symbol> {
+    bytes.iter().map(|&b| Symbol::from(b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::lexer::byte_scanner::{ByteScanner, ByteToken};
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    fn layout(input: &str) -> Layout<ByteScanner<Cursor<&str>>, ByteToken> {
+        Layout::new(ByteScanner::new(Cursor::new(input)), 1000, 1001, 1002)
+    }
+
+    fn show(input: &str) -> String {
+        let toks: Vec<_> = layout(input).map(|tok| tok.to_string()).collect();
+        toks.join(" ")
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            show("a\n  b\n\n \t\n  c\n\td\ne"),
+            "'a' NEWLINE INDENT 'b' NEWLINE 'c' NEWLINE INDENT 'd' NEWLINE DEDENT DEDENT 'e' NEWLINE"
+        );
+        assert_eq!(show("f(\n x)\n  y\n"), "'f' '(' '\n' ' ' 'x' ')' NEWLINE INDENT 'y' NEWLINE DEDENT");
+        assert_eq!(
+            show("\n  a\n    b\n  c\n"),
+            "INDENT 'a' NEWLINE INDENT 'b' NEWLINE DEDENT 'c' NEWLINE DEDENT"
+        );
+        assert_eq!(show("\n\n"), "");
+
+        let tok = LayoutToken::<ByteToken>::Dedent(1001);
+        assert_eq!(LayoutToken::<ByteToken>::from((tok.sym(), tok.value())), tok);
+    }
+
+    // block ::= stmt+
+    // stmt ::= [a-z] NEWLINE | [a-z] ':' NEWLINE INDENT block DEDENT
+    fn parses(input: &str) -> bool {
+        let mut g = Grammar::new().unwrap();
+        let indent = g.new_symbol().unwrap();
+        let dedent = g.new_symbol().unwrap();
+        let newline = g.new_symbol().unwrap();
+        let name = g.byte_range(None, b'a', b'z').unwrap();
+        let colon = g.literal_string(None, ":").unwrap();
+        let stmt = g.new_symbol().unwrap();
+        let block = g.plus(None, stmt).unwrap();
+        g.rule(Some(stmt), &[name, newline]).unwrap();
+        g.rule(Some(stmt), &[name, colon, newline, indent, block, dedent]).unwrap();
+        g.set_start(block).unwrap();
+
+        let (indent, dedent, newline) = (g.symbol(indent), g.symbol(dedent), g.symbol(newline));
+        let mut tokens = Layout::new(ByteScanner::new(Cursor::new(input)), indent, dedent, newline);
+        let parsed = Parser::with_grammar(g.unwrap()).run_recognizer(&mut tokens).is_ok();
+        tokens.check().is_ok() && parsed
+    }
+
+    #[test]
+    fn parse() {
+        assert!(parses("a:\n  b\n  c:\n\n    d\ne\n"));
+        assert!(!parses("a:\nb\n"));
+        assert!(!parses("a\n  b\n"));
+        assert!(!parses("a:\n    b\n  c\n"));
+    }
+
+    #[test]
+    fn inconsistent_dedent() {
+        let mut tokens = layout("    a\n  b\n");
+        let toks: Vec<_> = tokens.by_ref().map(|tok| tok.to_string()).collect();
+        assert_eq!(toks.join(" "), "INDENT 'a' NEWLINE");
+        match tokens.check().unwrap_err() {
+            Error::Parse { location, message } => {
+                assert_eq!(location, Position::Token(8));
+                assert!(message.contains("indentation"), "{}", message);
+            }
+            err => panic!("{}", err),
+        }
+        assert!(tokens.check().is_ok());
+    }
+}
@@ -2,5 +2,7 @@ extern crate regex;
 extern crate regex_syntax;
 
 pub mod byte_scanner;
+pub mod discard;
+pub mod layout;
 pub mod token;
 pub mod token_source;
@@ -1,8 +1,10 @@
+pub mod ast;
 pub mod error;
 pub mod grammar;
 pub mod lexer;
 pub mod parser;
 pub mod result;
+pub mod scanless;
 pub mod stack;
 pub mod thin;
 pub mod tree_builder;
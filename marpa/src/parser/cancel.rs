@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle to cancel parses from another thread. Clones share the same
+/// flag, so one can be kept to cancel with while another is given to
+/// `Limits::cancel_token`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Make parses with this token fail with a cancelled error at their next
+    /// check.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{Grammar, Item};
+    use crate::lexer::byte_scanner::{ByteScanner, ByteToken};
+    use crate::parser::{Limits, Parser};
+    use crate::stack::proc_value_limited;
+    use crate::tree_builder::TreeBuilder;
+    use std::io::Cursor;
+    use std::iter;
+
+    #[test]
+    fn cancel() {
+        let mut g = Grammar::new().unwrap();
+        let a_star = g.star(None, Item::Symbol(i32::from(b'a'))).unwrap();
+        g.set_start(a_star).unwrap();
+        let token = CancelToken::new();
+        let mut limits = Limits::new();
+        limits.cancel_token(token.clone());
+        let mut p = Parser::with_grammar(g.unwrap());
+        p.limits(limits.clone());
+
+        // the scanner cancels after 100 tokens, as another thread would
+        let canceller = token.clone();
+        let endless = iter::repeat(ByteToken::from((i32::from(b'a'), 0))).enumerate().map(move |(i, tok)| {
+            if i == 100 {
+                canceller.cancel();
+            }
+            tok
+        });
+        let err = p.run_recognizer(endless).err().unwrap();
+        assert!(err.is_cancelled());
+        assert!(err.limit().is_none());
+
+        // the recognizer is gone, but the grammar can be used again
+        p.limits(Limits::new());
+        let mut tree = p.run_recognizer(ByteScanner::new(Cursor::new("aaa"))).unwrap();
+        let value = tree.next().unwrap();
+        assert!(proc_value_limited(TreeBuilder::new(), value, &limits).err().unwrap().is_cancelled());
+    }
+}
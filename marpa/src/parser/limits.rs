@@ -0,0 +1,238 @@
+use crate::error::{Error, Limit};
+use crate::parser::CancelToken;
+use crate::result::Result;
+use crate::thin::{Tree, Value};
+use std::time::{Duration, Instant};
+
+/// Caps on the resources a parse may use, for untrusted input, along with a
+/// token to cancel it with. There are no limits by default.
+///
+/// `Parser` and `scanless::ScanlessParser` check the input length, Earley
+/// items, deadline and cancel token while recognizing. The parse count is
+/// checked by `Limits::parses`, and the tree depth and cancel token by
+/// `stack::proc_value_limited`.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    input_length: Option<usize>,
+    earley_items: Option<usize>,
+    set_items: Option<usize>,
+    parses: Option<usize>,
+    depth: Option<usize>,
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
+}
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    /// The most tokens to read.
+    pub fn max_input_length(&mut self, max: usize) {
+        self.input_length = Some(max);
+    }
+
+    /// The most Earley items in all sets together.
+    pub fn max_earley_items(&mut self, max: usize) {
+        self.earley_items = Some(max);
+    }
+
+    /// The most Earley items in any one set.
+    pub fn max_set_items(&mut self, max: usize) {
+        self.set_items = Some(max);
+    }
+
+    /// The most parses to take from a tree.
+    pub fn max_parses(&mut self, max: usize) {
+        self.parses = Some(max);
+    }
+
+    /// The deepest a parse tree may be, counting rules and tokens.
+    pub fn max_depth(&mut self, max: usize) {
+        self.depth = Some(max);
+    }
+
+    /// When to stop recognizing. It's checked between earlemes.
+    pub fn deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Stop recognizing `timeout` from now.
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.deadline(Instant::now() + timeout);
+    }
+
+    /// Stop once `token` is cancelled. It's checked between earlemes, and
+    /// between the steps of an evaluation.
+    pub fn cancel_token(&mut self, token: CancelToken) {
+        self.cancel = Some(token);
+    }
+
+    /// The parses of `tree`, ending with an error if there are more than
+    /// the maximum.
+    pub fn parses(&self, tree: Tree) -> Parses {
+        Parses {
+            tree,
+            left: self.parses,
+            done: false,
+        }
+    }
+
+    pub(crate) fn check(&self, limit: Limit, value: usize) -> Result<()> {
+        let max = match limit {
+            Limit::InputLength => self.input_length,
+            Limit::EarleyItems => self.earley_items,
+            Limit::EarleySetItems => self.set_items,
+            Limit::Parses => self.parses,
+            Limit::TreeDepth => self.depth,
+            Limit::Deadline => return self.check_deadline(),
+        };
+        match max {
+            Some(max) if value > max => Err(limit.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<()> {
+        match self.cancel {
+            Some(ref token) if token.is_cancelled() => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_deadline(&self) -> Result<()> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Limit::Deadline.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The parses of a tree, from `Limits::parses`.
+pub struct Parses {
+    tree: Tree,
+    left: Option<usize>,
+    done: bool,
+}
+
+impl Iterator for Parses {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Result<Value>> {
+        if self.done {
+            return None;
+        }
+        let value = self.tree.next();
+        match self.left {
+            _ if value.is_none() => None,
+            Some(0) => {
+                self.done = true;
+                Some(Err(Limit::Parses.into()))
+            }
+            Some(ref mut left) => {
+                *left -= 1;
+                value.map(Ok)
+            }
+            None => value.map(Ok),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{Grammar, Item};
+    use crate::lexer::byte_scanner::{ByteScanner, ByteToken};
+    use crate::parser::Parser;
+    use crate::stack::proc_value_limited;
+    use crate::stack::processor::Processor;
+    use crate::thin::{Rule, Symbol};
+    use std::io::Cursor;
+
+    // s ::= s s | 'a', where a string of n `a`s has Catalan(n - 1) parses
+    fn parse(input: &str, limits: Limits) -> Result<Tree> {
+        let mut g = Grammar::new()?;
+        let s = g.new_symbol()?;
+        g.rule(Some(s), &[s, s])?;
+        g.rule(Some(s), &[Item::Symbol(i32::from(b'a'))])?;
+        g.set_start(s)?;
+        let mut p = Parser::with_grammar(g.unwrap());
+        p.limits(limits);
+        p.run_recognizer(ByteScanner::new(Cursor::new(input)))
+    }
+
+    fn limit<T>(res: Result<T>) -> Option<Limit> {
+        res.err().and_then(|e| e.limit())
+    }
+
+    #[test]
+    fn recognizer_limits() {
+        let input = "a".repeat(10);
+        assert!(parse(&input, Limits::new()).is_ok());
+
+        let mut limits = Limits::new();
+        limits.max_input_length(9);
+        assert_eq!(limit(parse(&input, limits)), Some(Limit::InputLength));
+
+        let mut limits = Limits::new();
+        limits.max_set_items(10);
+        assert_eq!(limit(parse(&input, limits)), Some(Limit::EarleySetItems));
+
+        let mut limits = Limits::new();
+        limits.max_earley_items(30);
+        assert_eq!(limit(parse(&input, limits)), Some(Limit::EarleyItems));
+
+        let mut limits = Limits::new();
+        limits.timeout(Duration::from_secs(0));
+        let err = parse(&input, limits).err().unwrap();
+        assert_eq!(err.limit(), Some(Limit::Deadline));
+        assert_eq!(err.to_string(), "deadline limit exceeded");
+    }
+
+    struct Leaves;
+
+    impl Processor for Leaves {
+        type Token = ByteToken;
+        type Tree = usize;
+
+        fn proc_rule(&mut self, _: Rule, children: Vec<usize>) -> usize {
+            children.iter().sum()
+        }
+
+        fn proc_token(&mut self, _: ByteToken) -> usize {
+            1
+        }
+
+        fn proc_null(&mut self, _: Symbol) -> usize {
+            0
+        }
+    }
+
+    // A tree can't move on while its last value is alive, so they're dropped.
+    fn parses(limits: &Limits) -> Vec<Result<()>> {
+        let tree = parse("aaaa", Limits::new()).unwrap();
+        limits.parses(tree).map(|value| value.map(drop)).collect()
+    }
+
+    #[test]
+    fn evaluation_limits() {
+        let mut limits = Limits::new();
+        limits.max_parses(2);
+        let first = parses(&limits);
+        assert_eq!(first.len(), 3);
+        assert!(first[0].is_ok() && first[1].is_ok());
+        assert_eq!(first[2].as_ref().err().and_then(|e| e.limit()), Some(Limit::Parses));
+
+        limits.max_parses(5);
+        let all = parses(&limits);
+        assert_eq!(all.len(), 5);
+        assert!(all.iter().all(|p| p.is_ok()));
+
+        // each parse of four `a`s is at least three rules deep, and a token
+        let value = || parse("aaaa", Limits::new()).unwrap().next().unwrap();
+        limits.max_depth(3);
+        assert_eq!(limit(proc_value_limited(Leaves, value(), &limits)), Some(Limit::TreeDepth));
+        limits.max_depth(20);
+        assert_eq!(proc_value_limited(Leaves, value(), &limits).unwrap(), 4);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::{Error, Limit, Position};
 use crate::lexer::token::Token;
 use crate::lexer::token_source::TokenSource;
 
@@ -5,6 +6,8 @@ use crate::result::Result;
 
 use crate::thin::{
     Bocage,
+    EarleySet,
+    Event,
     Grammar,
     Order,
     Recognizer,
@@ -12,6 +15,12 @@ use crate::thin::{
     // Value,
 };
 
+mod cancel;
+mod limits;
+
+pub use self::cancel::CancelToken;
+pub use self::limits::{Limits, Parses};
+
 #[allow(dead_code)]
 enum MarpaState {
     G(Grammar),
@@ -31,7 +40,9 @@ impl MarpaState {
     fn adv(&mut self) -> Result<MarpaState> {
         match self {
             G(ref mut g) => {
-                g.precompute()?;
+                if !g.is_precomputed()? {
+                    g.precompute()?;
+                }
                 Recognizer::new(g.clone()).map(R)
             }
             R(ref r) => Bocage::new(r.clone()).map(B),
@@ -48,9 +59,21 @@ impl Default for MarpaState {
     }
 }
 
+/// What to do when an Earley set gets more items than the threshold given to
+/// `Parser::earley_item_threshold`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThresholdAction {
+    Continue,
+    Abort,
+}
+
+type ThresholdCallback = Box<dyn FnMut(EarleySet, i32) -> ThresholdAction>;
+
 #[derive(Default)]
 pub struct Parser {
     state: MarpaState,
+    threshold: Option<(i32, ThresholdCallback)>,
+    limits: Limits,
 }
 
 macro_rules! get_state {
@@ -68,7 +91,27 @@ impl Parser {
     }
 
     pub fn with_grammar(g: Grammar) -> Self {
-        Parser { state: G(g) }
+        Parser {
+            state: G(g),
+            threshold: None,
+            limits: Limits::new(),
+        }
+    }
+
+    /// Call `on_exceeded` with the Earley set and its number of items when a
+    /// set gets more than `threshold` items, which happens with very
+    /// ambiguous input. Parsing stops with `Error::EarleyItemThreshold` if it
+    /// returns `ThresholdAction::Abort`.
+    pub fn earley_item_threshold<F>(&mut self, threshold: i32, on_exceeded: F)
+    where
+        F: FnMut(EarleySet, i32) -> ThresholdAction + 'static,
+    {
+        self.threshold = Some((threshold, Box::new(on_exceeded)));
+    }
+
+    /// Fail when recognizing goes over `limits`.
+    pub fn limits(&mut self, limits: Limits) {
+        self.limits = limits;
     }
 
     fn adv_marpa(&mut self) -> Result<()> {
@@ -78,37 +121,176 @@ impl Parser {
 
     pub fn run_recognizer<T: TokenSource<U>, U: Token>(&mut self, tokens: T) -> Result<Tree> {
         let mut tokens = tokens;
-        if let G(_) = self.state {
+        let grammar = match self.state {
+            G(ref g) => Some(g.clone()),
+            _ => None,
+        };
+        if grammar.is_some() {
             self.adv_marpa()?
         }
+        match self.recognize(&mut tokens).and_then(|()| self.tree()) {
+            Ok(tree) => Ok(tree),
+            Err(err) => {
+                // drop the recognizer, leaving the parser ready for more input
+                if let Some(g) = grammar {
+                    self.state = G(g);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn tree(&mut self) -> Result<Tree> {
+        loop {
+            self.adv_marpa()?;
+            if let T(ref tree) = self.state {
+                return Ok(tree.clone());
+            }
+        }
+    }
+
+    fn recognize<T: TokenSource<U>, U: Token>(&mut self, mut tokens: T) -> Result<()> {
         {
             // limit recognizer borrow
+            let threshold = &mut self.threshold;
+            let limits = &self.limits;
             let r = get_state!(self, R);
+            if let Some((items, _)) = *threshold {
+                r.earley_item_warning_threshold_set(items);
+            }
             r.start_input()?;
+            let (mut read, mut items) = (0, 0);
             loop {
+                limits.check_deadline()?;
+                limits.check_cancelled()?;
+                let tok = match tokens.next() {
+                    None => break,
+                    Some(tok) => tok,
+                };
+                limits.check(Limit::InputLength, read + 1)?;
                 if r.is_exhausted() {
-                    break;
+                    return Err(Error::Parse {
+                        location: Position::Earleme(r.current_earleme()? as usize),
+                        message: format!("parse exhausted with input left, starting at {}", tok),
+                    });
                 }
-                let maybe_tok = tokens.next();
-                match maybe_tok {
-                    None => break,
-                    Some(tok) => {
-                        Parser::consume_tok(r, tok)?;
-                    }
+                Parser::consume_tok(r, tok)?;
+                read += 1;
+                if let Some((_, ref mut on_exceeded)) = *threshold {
+                    Parser::check_threshold(r, on_exceeded)?;
                 }
+                let size = r.earley_set_size(r.latest_earley_set()?)? as usize;
+                items += size;
+                limits.check(Limit::EarleySetItems, size)?;
+                limits.check(Limit::EarleyItems, items)?;
             }
         }
-        loop {
-            self.adv_marpa()?;
-            if let T(ref tree) = self.state {
-                return Ok(tree.clone());
+        Ok(())
+    }
+
+    fn check_threshold(r: &Recognizer, on_exceeded: &mut ThresholdCallback) -> Result<()> {
+        for event in r.events()? {
+            if let Event::EarleyItemThreshold(items) = event {
+                let set = r.latest_earley_set()?;
+                if on_exceeded(set, items) == ThresholdAction::Abort {
+                    return Err(Error::EarleyItemThreshold { set, items });
+                }
             }
         }
+        Ok(())
     }
 
     fn consume_tok<U: Token>(r: &mut Recognizer, tok: U) -> Result<()> {
-        r.alternative(tok.sym(), tok.value(), 1)?;
+        if let Err(err) = r.alternative(tok.sym(), tok.value(), 1) {
+            if !err.is_rejected_token() {
+                return Err(err);
+            }
+            return Err(Error::Parse {
+                location: Position::Earleme(r.current_earleme()? as usize),
+                message: format!("unexpected token {}", tok),
+            });
+        }
         r.earleme_complete()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{self, Item};
+    use crate::lexer::byte_scanner::ByteScanner;
+    use std::cell::Cell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    fn parse(input: &str) -> Result<()> {
+        let mut g = grammar::Grammar::new()?;
+        let ab = g.literal_string(None, "ab")?;
+        g.set_start(ab)?;
+        let mut p = Parser::with_grammar(g.unwrap());
+        p.run_recognizer(ByteScanner::new(Cursor::new(input))).map(|_| ())
+    }
+
+    #[test]
+    fn exhausted() {
+        assert!(parse("ab").is_ok());
+        match parse("abc").unwrap_err() {
+            Error::Parse { location, message } => {
+                assert_eq!(location, Position::Earleme(2));
+                assert!(message.contains("'c'"), "{}", message);
+            }
+            err => panic!("{}", err),
+        }
+        assert!(parse("ab \n").is_err());
+        match parse("ac").unwrap_err() {
+            Error::Parse { location, message } => {
+                assert_eq!(location, Position::Earleme(1));
+                assert_eq!(message, "unexpected token 'c'");
+            }
+            err => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn reuse_after_error() {
+        let mut g = grammar::Grammar::new().unwrap();
+        let ab = g.literal_string(None, "ab").unwrap();
+        g.set_start(ab).unwrap();
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut run = |input: &str| p.run_recognizer(ByteScanner::new(Cursor::new(input))).map(|_| ());
+        assert!(run("abc").is_err());
+        assert!(run("a").is_err());
+        assert!(run("ab").is_ok());
+    }
+
+    // s ::= s s | 'a', which is very ambiguous
+    fn ambiguous(on_exceeded: impl FnMut(EarleySet, i32) -> ThresholdAction + 'static) -> Result<()> {
+        let mut g = grammar::Grammar::new()?;
+        let s = g.new_symbol()?;
+        g.rule(Some(s), &[s, s])?;
+        g.rule(Some(s), &[Item::Symbol(i32::from(b'a'))])?;
+        g.set_start(s)?;
+        let mut p = Parser::with_grammar(g.unwrap());
+        p.earley_item_threshold(20, on_exceeded);
+        p.run_recognizer(ByteScanner::new(Cursor::new("a".repeat(20)))).map(|_| ())
+    }
+
+    #[test]
+    fn threshold() {
+        let exceeded = Rc::new(Cell::new(0));
+        let count = exceeded.clone();
+        let res = ambiguous(move |_, items| {
+            assert!(items > 20);
+            count.set(count.get() + 1);
+            ThresholdAction::Continue
+        });
+        assert!(res.is_ok());
+        assert!(exceeded.get() > 0);
+
+        match ambiguous(|_, _| ThresholdAction::Abort).unwrap_err() {
+            Error::EarleyItemThreshold { set, items } => assert!(set > 0 && items > 20),
+            err => panic!("{}", err),
+        }
+    }
+}
@@ -0,0 +1,715 @@
+//! Two-level parsing, where a lexical grammar (L0) reads lexemes from bytes,
+//! and a structural grammar (G1) takes them as its terminals.
+
+use crate::error::{Error, Limit, Position};
+use crate::grammar::{Grammar, Item, Names};
+use crate::lexer::discard::Discard;
+use crate::lexer::token::Token;
+use crate::parser::Limits;
+use crate::result::Result;
+use crate::thin::{self, Bocage, Order, Recognizer, Symbol, Tree};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+// What an L0 top rule recognizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Top {
+    Lexeme(Symbol),
+    Discard,
+}
+
+// How a lexeme is read, by G1 symbol.
+#[derive(Clone, Copy, Debug, Default)]
+struct LexemeOpts {
+    priority: i32,
+    before: bool,
+    after: bool,
+}
+
+/// The two grammars of a scanless parser.
+///
+/// L0 is a byte-level grammar, built like any other `grammar::Grammar`.
+/// Its items are declared as lexemes, each of which gets a terminal symbol in
+/// G1, or as discards, which are skipped between lexemes.
+pub struct ScanlessGrammar {
+    l0: Grammar,
+    g1: Grammar,
+    tops: Vec<(Item, Top)>,
+    opts: HashMap<Symbol, LexemeOpts>,
+    discard: Discard,
+}
+
+impl ScanlessGrammar {
+    pub fn new() -> Result<ScanlessGrammar> {
+        Ok(ScanlessGrammar {
+            l0: Grammar::new()?,
+            g1: Grammar::new()?,
+            tops: vec![],
+            opts: HashMap::new(),
+            discard: Discard::new(),
+        })
+    }
+
+    /// The lexical grammar.
+    pub fn l0(&mut self) -> &mut Grammar {
+        &mut self.l0
+    }
+
+    /// The structural grammar, whose start symbol is the start of the parse.
+    pub fn g1(&mut self) -> &mut Grammar {
+        &mut self.g1
+    }
+
+    /// Declare the L0 `pattern` as a lexeme, returning its G1 symbol.
+    pub fn lexeme<S: Into<String>>(&mut self, name: S, pattern: Item) -> Result<Item> {
+        let sym = self.g1.new_symbol()?;
+        self.g1.name(sym, name);
+        self.tops.push((pattern, Top::Lexeme(self.g1.symbol(sym))));
+        Ok(sym)
+    }
+
+    /// Skip input matching the L0 `pattern` wherever a lexeme could start.
+    pub fn discard(&mut self, pattern: Item) {
+        self.tops.push((pattern, Top::Discard));
+    }
+
+    /// Skip input matching the patterns of `discard` wherever a lexeme could
+    /// start, like the L0 discards.
+    pub fn discard_patterns(&mut self, discard: Discard) {
+        self.discard = discard;
+    }
+
+    /// When lexemes of the same length match, only those with the highest
+    /// priority are read. Lexemes start with a priority of 0.
+    pub fn priority(&mut self, lexeme: Item, priority: i32) {
+        self.opts(lexeme).priority = priority;
+    }
+
+    /// Pause reading before `lexeme` with a `LexemeEvent::Before`.
+    pub fn before_lexeme(&mut self, lexeme: Item) {
+        self.opts(lexeme).before = true;
+    }
+
+    /// Pause reading after `lexeme` with a `LexemeEvent::After`.
+    pub fn after_lexeme(&mut self, lexeme: Item) {
+        self.opts(lexeme).after = true;
+    }
+
+    fn opts(&mut self, lexeme: Item) -> &mut LexemeOpts {
+        let sym = self.g1.symbol(lexeme);
+        self.opts.entry(sym).or_default()
+    }
+}
+
+/// A lexeme read from the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lexeme {
+    /// The lexeme's G1 symbol.
+    pub symbol: Symbol,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Where a `Reader` paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LexemeEvent {
+    /// A lexeme was found, but not read yet. Resuming reads it, unless the
+    /// position was changed or another lexeme was read first.
+    Before(Lexeme),
+    /// A lexeme was read.
+    After(Lexeme),
+}
+
+/// The token G1 gets for a lexeme. Its value is the lexeme's index in
+/// `Parse::lexemes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LexemeToken {
+    sym: Symbol,
+    index: usize,
+}
+
+impl LexemeToken {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl From<(Symbol, i32)> for LexemeToken {
+    fn from((sym, val): (Symbol, i32)) -> Self {
+        LexemeToken {
+            sym,
+            index: (val - 1) as usize,
+        }
+    }
+}
+
+impl Token for LexemeToken {
+    fn sym(&self) -> Symbol {
+        self.sym
+    }
+
+    fn value(&self) -> i32 {
+        self.index as i32 + 1
+    }
+}
+
+impl fmt::Display for LexemeToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// A scanless parser, with both of its grammars precomputed.
+///
+/// libmarpa recognizers can't be restarted, so every lexeme is read with a
+/// new L0 recognizer. That costs a few allocations per lexeme, on top of
+/// matching it.
+pub struct ScanlessParser {
+    l0: thin::Grammar,
+    g1: thin::Grammar,
+    tops: HashMap<thin::Rule, Top>,
+    opts: HashMap<Symbol, LexemeOpts>,
+    discard: Discard,
+    names: Names,
+    limits: Limits,
+}
+
+impl ScanlessParser {
+    pub fn new(grammar: ScanlessGrammar) -> Result<ScanlessParser> {
+        let ScanlessGrammar {
+            mut l0,
+            g1,
+            tops,
+            opts,
+            discard,
+        } = grammar;
+        // Each pattern gets a symbol of its own between it and the start, so
+        // patterns can be shared by lexemes without duplicating rules.
+        let start = l0.new_symbol()?;
+        let mut rules = HashMap::new();
+        for (pattern, top) in tops {
+            let lhs = l0.rule(None, &[pattern])?;
+            let rule = l0.rule(Some(start), &[lhs])?;
+            rules.insert(rule.rule(), top);
+        }
+        l0.set_start(start)?;
+
+        let names = g1.names().clone();
+        let (mut l0, mut g1) = (l0.unwrap(), g1.unwrap());
+        l0.precompute()?;
+        g1.precompute()?;
+        Ok(ScanlessParser {
+            l0,
+            g1,
+            tops: rules,
+            opts,
+            discard,
+            names,
+            limits: Limits::new(),
+        })
+    }
+
+    fn opts(&self, sym: Symbol) -> LexemeOpts {
+        self.opts.get(&sym).cloned().unwrap_or_default()
+    }
+
+    /// Fail when reading goes over `limits`. The input length is in bytes,
+    /// and the deadline and cancel token are checked between lexemes and
+    /// discards.
+    pub fn limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// The names given to G1's items, including the lexemes.
+    pub fn names(&self) -> &Names {
+        &self.names
+    }
+
+    /// Start reading `input`.
+    pub fn reader<'a>(&'a self, input: &'a [u8]) -> Result<Reader<'a>> {
+        self.limits.check(Limit::InputLength, input.len())?;
+        let mut g1 = Recognizer::new(self.g1.clone())?;
+        g1.start_input()?;
+        Ok(Reader {
+            parser: self,
+            input,
+            pos: 0,
+            g1,
+            lexemes: vec![],
+            events: VecDeque::new(),
+            pending: vec![],
+            items: 0,
+        })
+    }
+
+    /// Read all of `input`, ignoring events.
+    pub fn parse<'a>(&'a self, input: &'a [u8]) -> Result<Parse<'a>> {
+        let mut reader = self.reader(input)?;
+        while reader.read()?.is_some() {}
+        reader.finish()
+    }
+}
+
+/// The state of a scanless parse of one input.
+pub struct Reader<'a> {
+    parser: &'a ScanlessParser,
+    input: &'a [u8],
+    pos: usize,
+    g1: Recognizer,
+    lexemes: Vec<Lexeme>,
+    events: VecDeque<LexemeEvent>,
+    // lexemes found at `pos` whose before events were returned
+    pending: Vec<Lexeme>,
+    // Earley items in G1 so far
+    items: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// The offset of the next byte to read.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Move to `pos` in the input, to read from there next.
+    pub fn set_pos(&mut self, pos: usize) {
+        self.pos = pos.min(self.input.len());
+        self.pending.clear();
+    }
+
+    /// Read lexemes up to the end of the input, or until an event. Returns
+    /// `None` at the end of the input.
+    pub fn read(&mut self) -> Result<Option<LexemeEvent>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            if !self.pending.is_empty() {
+                let found = ::std::mem::take(&mut self.pending);
+                self.accept(found)?;
+                continue;
+            }
+            if self.pos >= self.input.len() {
+                return Ok(None);
+            }
+            self.parser.limits.check_deadline()?;
+            self.parser.limits.check_cancelled()?;
+
+            // the longest match wins, and a lexeme wins a tie with a discard.
+            // An exhausted parse expects no lexemes, but can still skip
+            // discards up to the end of the input.
+            let skip = self.parser.discard.longest_match(&self.input[self.pos..]);
+            let (len, found) = match (self.lex()?, skip) {
+                (Some((len, _)), Some(skip)) if skip > len => (skip, vec![]),
+                (Some(lexed), _) => lexed,
+                (None, Some(skip)) => (skip, vec![]),
+                (None, None) => {
+                    let message = if self.g1.is_exhausted() { "parse exhausted" } else { "no lexeme" };
+                    return Err(Error::Parse {
+                        location: Position::Byte(self.pos),
+                        message: message.into(),
+                    });
+                }
+            };
+            let (start, end) = (self.pos, self.pos + len);
+            if found.is_empty() {
+                self.pos = end;
+                continue;
+            }
+            let found: Vec<_> = found.into_iter().map(|symbol| Lexeme { symbol, start, end }).collect();
+            let parser = self.parser;
+            let before = found.iter().filter(|l| parser.opts(l.symbol).before);
+            self.events.extend(before.cloned().map(LexemeEvent::Before));
+            if self.events.is_empty() {
+                self.accept(found)?;
+            } else {
+                self.pending = found;
+            }
+        }
+    }
+
+    /// Read a lexeme for `symbol` spanning `start..end` of the input, and
+    /// move to its end. The lexeme needn't have an L0 pattern, so any G1
+    /// terminal can be read this way.
+    pub fn lexeme_read(&mut self, symbol: Symbol, start: usize, end: usize) -> Result<()> {
+        if start > end || end > self.input.len() {
+            return Err(format!("lexeme span {}..{} is out of the input", start, end).into());
+        }
+        self.pending.clear();
+        self.accept(vec![Lexeme { symbol, start, end }])
+    }
+
+    // Reads lexemes that all end at the same place as alternatives.
+    fn accept(&mut self, found: Vec<Lexeme>) -> Result<()> {
+        for lexeme in found {
+            let index = self.lexemes.len();
+            self.lexemes.push(lexeme);
+            if let Err(err) = self.g1.alternative(lexeme.symbol, index as i32 + 1, 1) {
+                if !err.is_rejected_token() {
+                    return Err(err);
+                }
+                return Err(Error::Parse {
+                    location: Position::Byte(lexeme.start),
+                    message: format!("unexpected lexeme {}", lexeme.symbol),
+                });
+            }
+            self.pos = lexeme.end;
+            if self.parser.opts(lexeme.symbol).after {
+                self.events.push_back(LexemeEvent::After(lexeme));
+            }
+        }
+        self.g1.earleme_complete()?;
+        let limits = &self.parser.limits;
+        let size = self.g1.earley_set_size(self.g1.latest_earley_set()?)? as usize;
+        self.items += size;
+        limits.check(Limit::EarleySetItems, size)?;
+        limits.check(Limit::EarleyItems, self.items)
+    }
+
+    /// The parse of the input read so far.
+    pub fn finish(self) -> Result<Parse<'a>> {
+        let tree = Tree::new(Order::new(Bocage::new(self.g1)?)?)?;
+        Ok(Parse {
+            tree,
+            input: self.input,
+            lexemes: self.lexemes,
+        })
+    }
+
+    // Finds the longest match at `pos` that G1 accepts, returning its length
+    // and its lexemes, which are empty for a discard. The L0 recognizer is
+    // new for every call, since it has to start at `pos`.
+    fn lex(&self) -> Result<Option<(usize, Vec<Symbol>)>> {
+        let expected = self.g1.terminals_expected()?;
+        let mut l0 = Recognizer::new(self.parser.l0.clone())?;
+        l0.start_input()?;
+
+        let mut best = None;
+        for (i, &byte) in self.input[self.pos..].iter().enumerate() {
+            let sym = Symbol::from(byte);
+            if !l0.terminal_is_expected(sym)? {
+                break;
+            }
+            l0.alternative(sym, sym + 1, 1)?;
+            l0.earleme_complete()?;
+
+            let mut found = vec![];
+            let mut discard = false;
+            for item in l0.progress_report(l0.latest_earley_set()?)? {
+                if item.pos != -1 || item.origin != 0 {
+                    continue;
+                }
+                match self.parser.tops.get(&item.rule) {
+                    Some(&Top::Lexeme(sym)) if expected.contains(&sym) => found.push(sym),
+                    Some(&Top::Discard) => discard = true,
+                    _ => {}
+                }
+            }
+            if !found.is_empty() || discard {
+                let priority = |sym: &Symbol| self.parser.opts(*sym).priority;
+                let max = found.iter().map(priority).max();
+                found.retain(|sym| Some(priority(sym)) == max);
+                found.sort();
+                found.dedup();
+                best = Some((i + 1, found));
+            }
+            if l0.is_exhausted() {
+                break;
+            }
+        }
+        Ok(best)
+    }
+}
+
+/// A finished scanless parse.
+pub struct Parse<'a> {
+    tree: Tree,
+    input: &'a [u8],
+    lexemes: Vec<Lexeme>,
+}
+
+impl<'a> Parse<'a> {
+    /// The G1 parse trees, whose tokens are `LexemeToken`s.
+    pub fn tree(&mut self) -> &mut Tree {
+        &mut self.tree
+    }
+
+    pub fn lexemes(&self) -> &[Lexeme] {
+        &self.lexemes
+    }
+
+    pub fn lexeme(&self, tok: LexemeToken) -> &Lexeme {
+        &self.lexemes[tok.index]
+    }
+
+    /// The input a token was read from.
+    pub fn text(&self, tok: LexemeToken) -> &'a [u8] {
+        let lexeme = self.lexeme(tok);
+        &self.input[lexeme.start..lexeme.end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::proc_value;
+    use crate::stack::processor::Processor;
+    use crate::thin::Rule;
+    use std::time::Duration;
+
+    struct Calc {
+        parser: ScanlessParser,
+        add: Rule,
+    }
+
+    // sum ::= sum '+' num | num, with whitespace and `#` comments discarded
+    fn calc() -> Calc {
+        let mut g = ScanlessGrammar::new().unwrap();
+        let digit = g.l0().byte_range(None, b'0', b'9').unwrap();
+        let digits = g.l0().plus(None, digit).unwrap();
+        let ws_byte = g.l0().byte_set(None, b" \n").unwrap();
+        let ws = g.l0().plus(None, ws_byte).unwrap();
+        let hash = g.l0().literal_string(None, "#").unwrap();
+        let not_nl = g.l0().inverse_byte_set(None, b"\n").unwrap();
+        let text = g.l0().star(None, not_nl).unwrap();
+        let comment = g.l0().rule(None, &[hash, text]).unwrap();
+        let plus_l0 = g.l0().literal_string(None, "+").unwrap();
+
+        let num = g.lexeme("num", digits).unwrap();
+        let plus = g.lexeme("plus", plus_l0).unwrap();
+        g.discard(ws);
+        g.discard(comment);
+
+        let sum = g.g1().new_symbol().unwrap();
+        let add = g.g1().rule(Some(sum), &[sum, plus, num]).unwrap();
+        g.g1().rule(Some(sum), &[num]).unwrap();
+        g.g1().set_start(sum).unwrap();
+
+        Calc {
+            parser: ScanlessParser::new(g).unwrap(),
+            add: add.rule(),
+        }
+    }
+
+    struct Eval<'p, 'a> {
+        parse: &'p Parse<'a>,
+        add: Rule,
+    }
+
+    impl<'p, 'a> Processor for Eval<'p, 'a> {
+        type Token = LexemeToken;
+        type Tree = i64;
+
+        fn proc_rule(&mut self, rule: Rule, children: Vec<i64>) -> i64 {
+            if rule == self.add {
+                children[0] + children[2]
+            } else {
+                children[0]
+            }
+        }
+
+        fn proc_token(&mut self, tok: LexemeToken) -> i64 {
+            ::std::str::from_utf8(self.parse.text(tok)).unwrap().parse().unwrap_or(0)
+        }
+
+        fn proc_null(&mut self, _: Symbol) -> i64 {
+            0
+        }
+    }
+
+    fn eval(calc: &Calc, input: &str) -> Result<i64> {
+        let mut parse = calc.parser.parse(input.as_bytes())?;
+        let value = parse.tree().next().unwrap();
+        Ok(proc_value(
+            Eval {
+                parse: &parse,
+                add: calc.add,
+            },
+            value,
+        ))
+    }
+
+    #[test]
+    fn scanless() {
+        let calc = calc();
+        assert_eq!(eval(&calc, "12 + 3+4").unwrap(), 19);
+        assert_eq!(eval(&calc, "  1 # one\n+ 20 # twenty\n").unwrap(), 21);
+
+        let parse = calc.parser.parse(b"12 + 3").unwrap();
+        let spans: Vec<_> = parse.lexemes().iter().map(|l| (l.start, l.end)).collect();
+        assert_eq!(spans, vec![(0, 2), (3, 4), (5, 6)]);
+        assert_eq!(calc.parser.names().symbol(parse.lexemes()[1].symbol), Some("plus"));
+
+        assert!(eval(&calc, "1 + x").is_err());
+        // `+` isn't expected after `1 +`, so it isn't a lexeme there
+        assert!(eval(&calc, "1 + + 2").is_err());
+        assert!(eval(&calc, "1 +").is_err());
+
+        // `+` can't be read at the start
+        let plus = calc.parser.names().symbols_named("plus").next().unwrap();
+        let mut reader = calc.parser.reader(b"+").unwrap();
+        match reader.lexeme_read(plus, 0, 1).unwrap_err() {
+            Error::Parse { location, .. } => assert_eq!(location, Position::Byte(0)),
+            err => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn shared_pattern() {
+        // pair ::= name value, where both lexemes are runs of letters
+        let mut g = ScanlessGrammar::new().unwrap();
+        let lower = g.l0().byte_range(None, b'a', b'z').unwrap();
+        let letters = g.l0().plus(None, lower).unwrap();
+        let space = g.l0().literal_string(None, " ").unwrap();
+        let name = g.lexeme("name", letters).unwrap();
+        let value = g.lexeme("value", letters).unwrap();
+        g.discard(space);
+        let pair = g.g1().rule(None, &[name, value]).unwrap();
+        g.g1().set_start(pair).unwrap();
+        let (name, value) = (g.g1().symbol(name), g.g1().symbol(value));
+        let parser = ScanlessParser::new(g).unwrap();
+
+        let found = lexemes(&parser, "ab cd", |_, _| Ok(())).unwrap();
+        assert_eq!(found, vec![(name, "ab".to_string()), (value, "cd".to_string())]);
+        assert_eq!(lexemes(&parser, "ab cd ", |_, _| Ok(())).unwrap(), found);
+    }
+
+    #[test]
+    fn trailing_discards() {
+        // pair ::= name value, which exhausts after its second lexeme
+        let mut g = ScanlessGrammar::new().unwrap();
+        let lower = g.l0().byte_range(None, b'a', b'z').unwrap();
+        let letters = g.l0().plus(None, lower).unwrap();
+        let name = g.lexeme("name", letters).unwrap();
+        let value = g.lexeme("value", letters).unwrap();
+        let mut discard = Discard::new();
+        discard.whitespace();
+        discard.line_comment("#");
+        g.discard_patterns(discard);
+        let pair = g.g1().rule(None, &[name, value]).unwrap();
+        g.g1().set_start(pair).unwrap();
+        let parser = ScanlessParser::new(g).unwrap();
+
+        assert_eq!(lexemes(&parser, "ab cd ", |_, _| Ok(())).unwrap().len(), 2);
+        assert_eq!(lexemes(&parser, "ab cd # pair\n\n", |_, _| Ok(())).unwrap().len(), 2);
+        match lexemes(&parser, "ab cd # pair\nef", |_, _| Ok(())).unwrap_err() {
+            Error::Parse { location, message } => assert_eq!((location, &*message), (Position::Byte(13), "parse exhausted")),
+            err => panic!("{}", err),
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let mut calc = calc();
+        let mut limits = Limits::new();
+        limits.max_input_length(8);
+        limits.max_earley_items(10);
+        calc.parser.limits(limits);
+        assert_eq!(eval(&calc, "1 + 2").unwrap(), 3);
+        assert_eq!(eval(&calc, "1 +     2").unwrap_err().limit(), Some(Limit::InputLength));
+        assert_eq!(eval(&calc, "1+2+3+4").unwrap_err().limit(), Some(Limit::EarleyItems));
+
+        // discards are read with the deadline checked between them
+        let mut limits = Limits::new();
+        limits.timeout(Duration::from_secs(0));
+        calc.parser.limits(limits);
+        assert_eq!(eval(&calc, "        ").unwrap_err().limit(), Some(Limit::Deadline));
+    }
+
+    // Reads `input`, handling events with `on_event`, and returns the symbols
+    // and text of its lexemes.
+    fn lexemes<F>(parser: &ScanlessParser, input: &str, mut on_event: F) -> Result<Vec<(Symbol, String)>>
+    where
+        F: FnMut(&mut Reader, LexemeEvent) -> Result<()>,
+    {
+        let mut reader = parser.reader(input.as_bytes())?;
+        while let Some(event) = reader.read()? {
+            on_event(&mut reader, event)?;
+        }
+        let parse = reader.finish()?;
+        Ok(parse.lexemes().iter().map(|l| (l.symbol, input[l.start..l.end].to_string())).collect())
+    }
+
+    #[test]
+    fn priorities() {
+        // items ::= (print word | word)+
+        let mut g = ScanlessGrammar::new().unwrap();
+        let lower = g.l0().byte_range(None, b'a', b'z').unwrap();
+        let letters = g.l0().plus(None, lower).unwrap();
+        let kw = g.l0().literal_string(None, "print").unwrap();
+        let space = g.l0().literal_string(None, " ").unwrap();
+        let word = g.lexeme("word", letters).unwrap();
+        let print = g.lexeme("print", kw).unwrap();
+        g.discard(space);
+        g.priority(print, 1);
+        g.before_lexeme(print);
+        let item = g.g1().new_symbol().unwrap();
+        g.g1().rule(Some(item), &[print, word]).unwrap();
+        g.g1().rule(Some(item), &[word]).unwrap();
+        let items = g.g1().plus(None, item).unwrap();
+        g.g1().set_start(items).unwrap();
+        let (word, print) = (g.g1().symbol(word), g.g1().symbol(print));
+        let parser = ScanlessParser::new(g).unwrap();
+
+        let mut events = vec![];
+        let found = lexemes(&parser, "print x printer", |reader, event| {
+            assert_eq!(reader.pos(), 0);
+            events.push(event);
+            Ok(())
+        })
+        .unwrap();
+        let text = |found: &[(Symbol, String)]| found.iter().map(|f| f.1.clone()).collect::<Vec<_>>();
+        assert_eq!(found.iter().map(|f| f.0).collect::<Vec<_>>(), vec![print, word, word]);
+        assert_eq!(text(&found), vec!["print", "x", "printer"]);
+        assert_eq!(
+            events,
+            vec![LexemeEvent::Before(Lexeme {
+                symbol: print,
+                start: 0,
+                end: 5
+            })]
+        );
+    }
+
+    #[test]
+    fn heredoc() {
+        // doc ::= (word | marker body)+, where the body is read by hand
+        let mut g = ScanlessGrammar::new().unwrap();
+        let lower = g.l0().byte_range(None, b'a', b'z').unwrap();
+        let letters = g.l0().plus(None, lower).unwrap();
+        let upper = g.l0().byte_range(None, b'A', b'Z').unwrap();
+        let tag = g.l0().plus(None, upper).unwrap();
+        let angles = g.l0().literal_string(None, "<<").unwrap();
+        let marker_l0 = g.l0().rule(None, &[angles, tag]).unwrap();
+        let ws_byte = g.l0().byte_set(None, b" \n").unwrap();
+        let ws = g.l0().plus(None, ws_byte).unwrap();
+        let word = g.lexeme("word", letters).unwrap();
+        let marker = g.lexeme("marker", marker_l0).unwrap();
+        g.discard(ws);
+        g.after_lexeme(marker);
+        let body = g.g1().new_symbol().unwrap();
+        let heredoc = g.g1().rule(None, &[marker, body]).unwrap();
+        let item = g.g1().alternative(None, &[word, heredoc]).unwrap();
+        let doc = g.g1().plus(None, item).unwrap();
+        g.g1().set_start(doc).unwrap();
+        let body = g.g1().symbol(body);
+        let parser = ScanlessParser::new(g).unwrap();
+
+        let input = "say <<END\nhello there\nEND\nbye\n";
+        let found = lexemes(&parser, input, |reader, event| {
+            let marker = match event {
+                LexemeEvent::After(lexeme) => lexeme,
+                _ => unreachable!(),
+            };
+            // the body runs from the next line up to a line with just the tag
+            let tag = &input[marker.start + 2..marker.end];
+            let start = marker.end + 1;
+            let end = start + input[start..].find(&format!("\n{}\n", tag)).unwrap() + 1;
+            reader.lexeme_read(body, start, end)?;
+            reader.set_pos(end + tag.len());
+            Ok(())
+        })
+        .unwrap();
+        let text: Vec<_> = found.iter().map(|f| f.1.as_str()).collect();
+        assert_eq!(text, vec!["say", "<<END", "hello there\n", "bye"]);
+    }
+}
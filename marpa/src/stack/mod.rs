@@ -1,65 +1,332 @@
-use crate::thin::Step;
+use crate::error::{Error, Limit};
+use crate::parser::Limits;
 use crate::thin::Value;
+use crate::thin::{Rule, Step, Symbol};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
 
 pub mod processor;
+pub mod semantics;
+pub mod walker;
 
-use self::processor::Processor;
+use self::processor::{Processor, TryProcessor};
+pub use self::semantics::Semantics;
+pub use self::walker::{stream, walk_buffered, Span, Walker};
+
+/// The part of the parse an action failed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Rule(Rule),
+    Token(Symbol),
+    Null(Symbol),
+}
+
+/// An error returned by an action during `try_proc_value`.
+#[derive(Debug)]
+pub struct EvalError<E> {
+    pub error: E,
+    pub location: Location,
+    /// The Earley sets where the failing part of the parse starts and ends.
+    pub span: Span,
+}
+
+impl<E: fmt::Display> fmt::Display for EvalError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (start, end) = self.span;
+        match self.location {
+            Location::Rule(rule) => write!(f, "rule {} at {}..{}: {}", rule, start, end, self.error),
+            Location::Token(sym) => write!(f, "token {} at {}..{}: {}", sym, start, end, self.error),
+            Location::Null(sym) => write!(f, "nulled symbol {} at {}..{}: {}", sym, start, end, self.error),
+        }
+    }
+}
+
+impl<E: ::std::error::Error + 'static> ::std::error::Error for EvalError<E> {
+    fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// Whether each RHS symbol of a rule is valued. Unvalued children have no
+// step of their own, so their slots are either unset or hold a value left
+// over from their descendants.
+//
+// A sequence has its item and then its separator, if any, which its
+// children alternate between.
+#[derive(Default)]
+struct ValuedChildren(HashMap<Rule, Vec<bool>>);
+
+impl ValuedChildren {
+    fn get(&mut self, val: &Value, rule: Rule) -> &[bool] {
+        self.0.entry(rule).or_insert_with(|| {
+            let mut rhs = val.rule_rhs(rule).expect("rule from a step");
+            if val.rule_is_sequence(rule).expect("rule from a step") {
+                rhs.extend(val.sequence_separator(rule).ok());
+            }
+            rhs.into_iter()
+                .map(|sym| val.symbol_is_valued(sym).expect("symbol from a rule"))
+                .collect()
+        })
+    }
+}
+
+// Move the values of the children in `start..=end` off of `slots`, dropping
+// whatever is in the slots of unvalued children. The children of a sequence
+// cycle through `valued`, the others have one flag each.
+fn take_children<V>(slots: &mut [Option<V>], valued: &[bool], start: usize, end: usize) -> Vec<V> {
+    (start..=end)
+        .filter_map(|loc| {
+            let slot = slots.get_mut(loc).and_then(Option::take);
+            if valued[(loc - start) % valued.len()] {
+                Some(slot.expect("a valued child has no value"))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
 
 struct Stack<T>
 where
-    T: Processor,
+    T: TryProcessor,
 {
-    items: Vec<T::Tree>,
+    items: Vec<Option<T::Tree>>,
     processor: T,
+    valued: ValuedChildren,
 }
 
 impl<T> Stack<T>
 where
-    T: Processor,
+    T: TryProcessor,
 {
     fn new(processor: T) -> Stack<T> {
-        let items = vec![Default::default(); 1];
-        Stack { items, processor }
+        Stack {
+            items: vec![],
+            processor,
+            valued: ValuedChildren::default(),
+        }
     }
 
-    fn step(&mut self, value_step: Step) {
+    fn step(&mut self, val: &Value, value_step: Step) -> Result<(), (T::Error, Location)> {
         match value_step {
             Step::Rule(rule, start, end) => {
-                self.size_stack(end as usize);
-                self.items[start as usize] = self.processor.proc_rule(rule, &self.items[start as usize..=end as usize]);
+                let valued = self.valued.get(val, rule);
+                let children = take_children(&mut self.items, valued, start as usize, end as usize);
+                let tree = self.processor.proc_rule(rule, children).map_err(|e| (e, Location::Rule(rule)))?;
+                self.put(start as usize, tree);
             }
             Step::Token(sym, res, val) => {
-                self.size_stack(res as usize);
-                self.items[res as usize] = self.processor.proc_token((sym, val).into());
+                let tree = self.processor.proc_token((sym, val).into()).map_err(|e| (e, Location::Token(sym)))?;
+                self.put(res as usize, tree);
             }
             Step::NullingSymbol(sym, res) => {
-                self.size_stack(res as usize);
-                self.items[res as usize] = self.processor.proc_null(sym);
+                let tree = self.processor.proc_null(sym).map_err(|e| (e, Location::Null(sym)))?;
+                self.put(res as usize, tree);
             }
-            s => panic!("Invalid step: {:?}", s),
+            Step::Initial | Step::Inactive => {}
         }
+        Ok(())
     }
 
-    fn size_stack(&mut self, last: usize) {
-        let size = last + 1;
-        let len = self.items.len();
-        if len < size {
-            self.items.reserve(size - len);
-            for _ in len..size {
-                self.items.push(Default::default());
-            }
+    fn put(&mut self, loc: usize, tree: T::Tree) {
+        if self.items.len() <= loc {
+            self.items.resize_with(loc + 1, || None);
         }
+        self.items[loc] = Some(tree);
     }
 
-    fn proc_value(&mut self, val: &mut Value) -> &T::Tree {
-        for v in val {
-            self.step(v);
+    fn proc_value(mut self, val: &mut Value) -> Result<T::Tree, EvalError<T::Error>> {
+        while let Some(v) = val.next() {
+            if let Err((error, location)) = self.step(val, v) {
+                let span = val.span();
+                return Err(EvalError { error, location, span });
+            }
         }
-        &self.items[0]
+        Ok(self.items.first_mut().and_then(Option::take).expect("the start symbol is unvalued"))
+    }
+}
+
+// Runs a `Processor` through the fallible evaluator.
+struct Infallibly<T>(T);
+
+impl<T: Processor> TryProcessor for Infallibly<T> {
+    type Token = T::Token;
+    type Tree = T::Tree;
+    type Error = Infallible;
+
+    fn proc_rule(&mut self, rule: Rule, children: Vec<T::Tree>) -> Result<T::Tree, Infallible> {
+        Ok(self.0.proc_rule(rule, children))
+    }
+
+    fn proc_token(&mut self, tok: T::Token) -> Result<T::Tree, Infallible> {
+        Ok(self.0.proc_token(tok))
+    }
+
+    fn proc_null(&mut self, sym: Symbol) -> Result<T::Tree, Infallible> {
+        Ok(self.0.proc_null(sym))
     }
 }
 
-pub fn proc_value<T: Processor>(eng: T, mut val: Value) -> T::Tree {
-    let mut stack = Stack::new(eng);
-    stack.proc_value(&mut val).clone()
+pub fn proc_value<T: Processor>(eng: T, val: Value) -> T::Tree {
+    match try_proc_value(Infallibly(eng), val) {
+        Ok(tree) => tree,
+        Err(e) => match e.error {},
+    }
+}
+
+/// Evaluate a parse with a `TryProcessor`, stopping at the first error.
+pub fn try_proc_value<T: TryProcessor>(eng: T, mut val: Value) -> Result<T::Tree, EvalError<T::Error>> {
+    Stack::new(eng).proc_value(&mut val)
+}
+
+// Tracks the depth of each tree, to stop at `Limits::max_depth`.
+struct Limited<'l, T> {
+    processor: T,
+    limits: &'l Limits,
+}
+
+impl<'l, T: Processor> Limited<'l, T> {
+    fn depth<C>(&self, children: &[(C, usize)]) -> Result<usize, Error> {
+        self.limits.check_cancelled()?;
+        let depth = children.iter().map(|c| c.1).max().unwrap_or(0) + 1;
+        self.limits.check(Limit::TreeDepth, depth)?;
+        Ok(depth)
+    }
+}
+
+impl<'l, T: Processor> TryProcessor for Limited<'l, T> {
+    type Token = T::Token;
+    type Tree = (T::Tree, usize);
+    type Error = Error;
+
+    fn proc_rule(&mut self, rule: Rule, children: Vec<Self::Tree>) -> Result<Self::Tree, Error> {
+        let depth = self.depth(&children)?;
+        let children = children.into_iter().map(|c| c.0).collect();
+        Ok((self.processor.proc_rule(rule, children), depth))
+    }
+
+    fn proc_token(&mut self, tok: T::Token) -> Result<Self::Tree, Error> {
+        let depth = self.depth::<()>(&[])?;
+        Ok((self.processor.proc_token(tok), depth))
+    }
+
+    fn proc_null(&mut self, sym: Symbol) -> Result<Self::Tree, Error> {
+        let depth = self.depth::<()>(&[])?;
+        Ok((self.processor.proc_null(sym), depth))
+    }
+}
+
+/// Evaluate a parse like `proc_value`, failing if the tree is deeper than
+/// `limits` allow, or their cancel token is cancelled.
+pub fn proc_value_limited<T: Processor>(eng: T, val: Value, limits: &Limits) -> crate::result::Result<T::Tree> {
+    let limited = Limited { processor: eng, limits };
+    try_proc_value(limited, val).map(|tree| tree.0).map_err(|e| e.error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{Grammar, Item};
+    use crate::lexer::byte_scanner::{ByteScanner, ByteToken};
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    struct Bytes(Rule);
+
+    impl TryProcessor for Bytes {
+        type Token = ByteToken;
+        type Tree = u8;
+        type Error = String;
+
+        fn proc_rule(&mut self, rule: Rule, children: Vec<u8>) -> Result<u8, String> {
+            if rule != self.0 {
+                return Ok(children[0]);
+            }
+            let text = String::from_utf8(children).unwrap();
+            text.parse().map_err(|_| format!("{} is too big", text))
+        }
+
+        fn proc_token(&mut self, tok: ByteToken) -> Result<u8, String> {
+            Ok(*tok)
+        }
+
+        fn proc_null(&mut self, _: Symbol) -> Result<u8, String> {
+            Ok(0)
+        }
+    }
+
+    fn eval(input: &str) -> (Result<u8, EvalError<String>>, Rule) {
+        let mut g = Grammar::new().unwrap();
+        let digit = g.char_range(None, '0', '9').unwrap();
+        let digits = g.sequence(None, digit, Item::Symbol(-1), true, false).unwrap();
+        let rule = g.rule(None, &[digits]).unwrap();
+        g.set_start(rule).unwrap();
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut t = p.run_recognizer(ByteScanner::new(Cursor::new(input))).unwrap();
+        (try_proc_value(Bytes(digits.rule()), t.next().unwrap()), digits.rule())
+    }
+
+    #[test]
+    fn try_proc_value_error() {
+        assert_eq!(eval("255").0.unwrap(), 255);
+
+        let (res, digits) = eval("256");
+        let err = res.unwrap_err();
+        assert_eq!(err.error, "256 is too big");
+        assert_eq!(err.span, (0, 3));
+        assert_eq!(err.location, Location::Rule(digits));
+        assert_eq!(err.to_string(), format!("rule {} at 0..3: 256 is too big", digits));
+    }
+
+    #[test]
+    fn unvalued() {
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "x").unwrap();
+        let sep = g.rule(None, &[]).unwrap();
+        let b = g.literal_string(None, "y").unwrap();
+        let c = g.literal_string(None, "z").unwrap();
+        let start = g.rule(None, &[a, sep, b, a, c]).unwrap();
+        g.set_start(start).unwrap();
+        g.unvalued(a).unwrap();
+        g.unvalued(sep).unwrap();
+        assert!(g.unvalued(Item::Symbol(i32::from(b'x'))).is_err());
+
+        let mut sem: Semantics<String> = Semantics::new();
+        sem.default_token(|tok: ByteToken| (*tok as char).to_string());
+        sem.default_rule(|children: Vec<String>| children.join(","));
+
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut t = p.run_recognizer(ByteScanner::new(Cursor::new("xyxz"))).unwrap();
+        assert_eq!(proc_value(sem, t.next().unwrap()), "y,z");
+    }
+
+    // `a+ % ','`, with `unvalue` choosing the item or the separator
+    fn separated(unvalue: fn(Item, Item) -> Item) -> String {
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "a").unwrap();
+        let comma = g.literal_string(None, ",").unwrap();
+        let list = g.sequence(None, a, comma, true, true).unwrap();
+        let start = g.rule(None, &[list]).unwrap();
+        g.set_start(start).unwrap();
+        g.unvalued(unvalue(a, comma)).unwrap();
+
+        let mut sem: Semantics<String> = Semantics::new();
+        sem.default_token(|tok: ByteToken| (*tok as char).to_string());
+        sem.default_rule(|children: Vec<String>| children.join("|"));
+
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut t = p.run_recognizer(ByteScanner::new(Cursor::new("a,a,a"))).unwrap();
+        proc_value(sem, t.next().unwrap())
+    }
+
+    #[test]
+    fn unvalued_separator() {
+        assert_eq!(separated(|_, comma| comma), "a|a|a");
+    }
+
+    #[test]
+    fn unvalued_item() {
+        assert_eq!(separated(|a, _| a), ",|,");
+    }
 }
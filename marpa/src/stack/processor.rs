@@ -2,11 +2,28 @@ use crate::lexer::token::Token;
 use crate::thin::Rule;
 use crate::thin::Symbol;
 
+/// Builds values bottom-up from the steps of a parse.
+///
+/// The values of a rule's children are moved into `proc_rule`, so trees
+/// don't need to be cloned or have a default.
 pub trait Processor {
     type Token: Token;
-    type Tree: Clone + Default;
+    type Tree;
 
-    fn proc_rule(&mut self, rule: Rule, children: &[Self::Tree]) -> Self::Tree;
+    fn proc_rule(&mut self, rule: Rule, children: Vec<Self::Tree>) -> Self::Tree;
     fn proc_token(&mut self, tok: Self::Token) -> Self::Tree;
     fn proc_null(&mut self, sym: Symbol) -> Self::Tree;
 }
+
+/// A `Processor` whose actions can fail.
+///
+/// Evaluation with `try_proc_value` stops at the first error.
+pub trait TryProcessor {
+    type Token: Token;
+    type Tree;
+    type Error;
+
+    fn proc_rule(&mut self, rule: Rule, children: Vec<Self::Tree>) -> Result<Self::Tree, Self::Error>;
+    fn proc_token(&mut self, tok: Self::Token) -> Result<Self::Tree, Self::Error>;
+    fn proc_null(&mut self, sym: Symbol) -> Result<Self::Tree, Self::Error>;
+}
@@ -0,0 +1,185 @@
+use crate::grammar::Item;
+use crate::lexer::byte_scanner::ByteToken;
+use crate::lexer::token::Token;
+use crate::stack::processor::Processor;
+use crate::thin::{Rule, Symbol};
+use std::collections::HashMap;
+
+type RuleAction<V> = Box<dyn FnMut(Vec<V>) -> V>;
+type TokenAction<V, T> = Box<dyn FnMut(T) -> V>;
+type NullAction<V> = Box<dyn FnMut() -> V>;
+
+/// A `Processor` built from per-rule, per-token and per-symbol actions.
+///
+/// Anything without its own action falls back to the default for its kind,
+/// which is `undef` for rules and `V::default()` for tokens and nulled
+/// symbols unless changed.
+pub struct Semantics<V, T = ByteToken> {
+    rules: HashMap<Rule, RuleAction<V>>,
+    tokens: HashMap<Symbol, TokenAction<V, T>>,
+    nulls: HashMap<Symbol, NullAction<V>>,
+    default_rule: RuleAction<V>,
+    default_token: TokenAction<V, T>,
+    default_null: NullAction<V>,
+}
+
+impl<V: Default + 'static, T: 'static> Default for Semantics<V, T> {
+    fn default() -> Self {
+        Semantics {
+            rules: Default::default(),
+            tokens: Default::default(),
+            nulls: Default::default(),
+            default_rule: Box::new(undef),
+            default_token: Box::new(|_| V::default()),
+            default_null: Box::new(V::default),
+        }
+    }
+}
+
+impl<V: Default + 'static, T: 'static> Semantics<V, T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl<V: 'static, T: 'static> Semantics<V, T> {
+    /// Set the action for a rule, as returned by the `Grammar` builders.
+    pub fn rule<F>(&mut self, rule: Item, action: F)
+    where
+        F: FnMut(Vec<V>) -> V + 'static,
+    {
+        self.rules.insert(rule.rule(), Box::new(action));
+    }
+
+    /// Set the action for tokens of a symbol.
+    pub fn token<F>(&mut self, sym: Symbol, action: F)
+    where
+        F: FnMut(T) -> V + 'static,
+    {
+        self.tokens.insert(sym, Box::new(action));
+    }
+
+    /// Set the value of a symbol when it matches the empty string.
+    pub fn nulled<F>(&mut self, sym: Symbol, action: F)
+    where
+        F: FnMut() -> V + 'static,
+    {
+        self.nulls.insert(sym, Box::new(action));
+    }
+
+    /// Set the action for rules without their own.
+    pub fn default_rule<F>(&mut self, action: F)
+    where
+        F: FnMut(Vec<V>) -> V + 'static,
+    {
+        self.default_rule = Box::new(action);
+    }
+
+    /// Set the action for tokens without their own.
+    pub fn default_token<F>(&mut self, action: F)
+    where
+        F: FnMut(T) -> V + 'static,
+    {
+        self.default_token = Box::new(action);
+    }
+
+    /// Set the value of nulled symbols without their own.
+    pub fn default_null<F>(&mut self, action: F)
+    where
+        F: FnMut() -> V + 'static,
+    {
+        self.default_null = Box::new(action);
+    }
+}
+
+impl<V, T: Token> Processor for Semantics<V, T> {
+    type Token = T;
+    type Tree = V;
+
+    fn proc_rule(&mut self, rule: Rule, children: Vec<V>) -> V {
+        match self.rules.get_mut(&rule) {
+            Some(action) => action(children),
+            None => (self.default_rule)(children),
+        }
+    }
+
+    fn proc_token(&mut self, tok: T) -> V {
+        match self.tokens.get_mut(&tok.sym()) {
+            Some(action) => action(tok),
+            None => (self.default_token)(tok),
+        }
+    }
+
+    fn proc_null(&mut self, sym: Symbol) -> V {
+        match self.nulls.get_mut(&sym) {
+            Some(action) => action(),
+            None => (self.default_null)(),
+        }
+    }
+}
+
+/// `::first`: the value of the first child.
+pub fn first<V: Default>(children: Vec<V>) -> V {
+    children.into_iter().next().unwrap_or_default()
+}
+
+/// `::last`: the value of the last child.
+pub fn last<V: Default>(children: Vec<V>) -> V {
+    children.into_iter().last().unwrap_or_default()
+}
+
+/// `::array`: the values of all of the children.
+pub fn array<V: From<Vec<V>>>(children: Vec<V>) -> V {
+    V::from(children)
+}
+
+/// `::undef`: no value.
+pub fn undef<V: Default>(_: Vec<V>) -> V {
+    V::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::lexer::byte_scanner::ByteScanner;
+    use crate::parser::Parser;
+    use crate::stack::proc_value;
+    use std::io::Cursor;
+
+    #[derive(Debug, Default, PartialEq)]
+    enum V {
+        #[default]
+        Undef,
+        Byte(u8),
+        List(Vec<V>),
+    }
+
+    impl From<Vec<V>> for V {
+        fn from(list: Vec<V>) -> Self {
+            V::List(list)
+        }
+    }
+
+    #[test]
+    fn actions() {
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "a").unwrap();
+        let b = g.literal_string(None, "b").unwrap();
+        let ab = g.rule(None, &[a, b]).unwrap();
+        let start = g.rule(None, &[ab, a]).unwrap();
+        g.set_start(start).unwrap();
+
+        let mut sem: Semantics<V> = Semantics::new();
+        sem.default_token(|tok: ByteToken| V::Byte(*tok));
+        sem.rule(a, first);
+        sem.rule(b, first);
+        sem.rule(ab, last);
+        sem.rule(start, array);
+
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut t = p.run_recognizer(ByteScanner::new(Cursor::new("aba"))).unwrap();
+        let value = proc_value(sem, t.next().unwrap());
+        assert_eq!(value, V::List(vec![V::Byte(b'b'), V::Byte(b'a')]));
+    }
+}
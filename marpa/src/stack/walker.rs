@@ -0,0 +1,195 @@
+use super::{take_children, ValuedChildren};
+use crate::lexer::token::Token;
+use crate::thin::{EarleySet, Rule, Step, Symbol, TokValue, Value};
+use std::ops::Range;
+
+/// The Earley sets where a part of the parse starts and ends.
+pub type Span = (EarleySet, EarleySet);
+
+/// Callbacks for `stream` and `walk_buffered`.
+///
+/// `stream` calls `rule` after the children of each rule, and never calls
+/// `enter_rule` or `exit_rule`. `walk_buffered` calls those two around the
+/// children instead, and never calls `rule`. Both call `token` and `nulled`
+/// in input order.
+///
+/// Every callback does nothing by default, so a walker only needs to
+/// implement the events it cares about.
+pub trait Walker {
+    type Token: Token;
+
+    fn rule(&mut self, _rule: Rule, _span: Span) {}
+    fn enter_rule(&mut self, _rule: Rule, _span: Span) {}
+    fn exit_rule(&mut self, _rule: Rule, _span: Span) {}
+    fn token(&mut self, _tok: Self::Token, _span: Span) {}
+    fn nulled(&mut self, _sym: Symbol, _span: Span) {}
+}
+
+/// Send each step of a parse to `walker` as it is produced, bottom-up.
+///
+/// Nothing is kept between steps, so this runs in constant memory however
+/// large the parse is. Use `walk_buffered` to get `enter_rule` before the
+/// children of a rule as well.
+pub fn stream<W: Walker>(walker: &mut W, mut val: Value) {
+    while let Some(step) = val.next() {
+        let span = val.span();
+        match step {
+            Step::Rule(rule, _, _) => walker.rule(rule, span),
+            Step::Token(sym, _, value) => walker.token((sym, value).into(), span),
+            Step::NullingSymbol(sym, _) => walker.nulled(sym, span),
+            Step::Initial | Step::Inactive => {}
+        }
+    }
+}
+
+enum Kind {
+    Rule(Rule, Range<u32>),
+    Token(Symbol, TokValue),
+    Null(Symbol),
+}
+
+struct Record {
+    kind: Kind,
+    span: Span,
+}
+
+/// Walk a parse top-down, calling `enter_rule` before and `exit_rule` after
+/// the children of each rule.
+///
+/// libmarpa produces steps bottom-up, with the root last, so every step is
+/// recorded before the first callback, using memory proportional to the
+/// size of the parse. The records are compact: no values are built and
+/// nothing is kept besides rule ids, tokens and spans.
+pub fn walk_buffered<W: Walker>(walker: &mut W, mut val: Value) {
+    let mut records: Vec<Record> = vec![];
+    let mut edges: Vec<u32> = vec![];
+    let mut slots: Vec<Option<u32>> = vec![];
+    let mut valued = ValuedChildren::default();
+
+    while let Some(step) = val.next() {
+        let span = val.span();
+        let (kind, slot) = match step {
+            Step::Rule(rule, start, end) => {
+                let from = edges.len() as u32;
+                let children = valued.get(&val, rule);
+                edges.extend(take_children(&mut slots, children, start as usize, end as usize));
+                (Kind::Rule(rule, from..edges.len() as u32), start)
+            }
+            Step::Token(sym, res, value) => (Kind::Token(sym, value), res),
+            Step::NullingSymbol(sym, res) => (Kind::Null(sym), res),
+            Step::Initial | Step::Inactive => continue,
+        };
+        let slot = slot as usize;
+        if slots.len() <= slot {
+            slots.resize(slot + 1, None);
+        }
+        slots[slot] = Some(records.len() as u32);
+        records.push(Record { kind, span });
+    }
+
+    let root = match slots.first() {
+        Some(&Some(root)) => root,
+        _ => return,
+    };
+
+    // (record, whether its children have been walked)
+    let mut stack = vec![(root, false)];
+    while let Some((id, done)) = stack.pop() {
+        let rec = &records[id as usize];
+        match rec.kind {
+            Kind::Rule(rule, _) if done => walker.exit_rule(rule, rec.span),
+            Kind::Rule(rule, ref children) => {
+                walker.enter_rule(rule, rec.span);
+                stack.push((id, true));
+                let children = &edges[children.start as usize..children.end as usize];
+                stack.extend(children.iter().rev().map(|&ch| (ch, false)));
+            }
+            Kind::Token(sym, value) => walker.token((sym, value).into(), rec.span),
+            Kind::Null(sym) => walker.nulled(sym, rec.span),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::Grammar;
+    use crate::lexer::byte_scanner::{ByteScanner, ByteToken};
+    use crate::parser::Parser;
+    use std::io::Cursor;
+
+    struct Events(Vec<String>);
+
+    impl Walker for Events {
+        type Token = ByteToken;
+
+        fn rule(&mut self, rule: Rule, (start, end): Span) {
+            self.0.push(format!("rule {} {}..{}", rule, start, end));
+        }
+
+        fn enter_rule(&mut self, rule: Rule, (start, end): Span) {
+            self.0.push(format!("enter {} {}..{}", rule, start, end));
+        }
+
+        fn exit_rule(&mut self, rule: Rule, _: Span) {
+            self.0.push(format!("exit {}", rule));
+        }
+
+        fn token(&mut self, tok: ByteToken, (start, end): Span) {
+            self.0.push(format!("token {} {}..{}", tok, start, end));
+        }
+
+        fn nulled(&mut self, sym: Symbol, _: Span) {
+            self.0.push(format!("nulled {}", sym));
+        }
+    }
+
+    fn events(walk: fn(&mut Events, Value)) -> (Vec<String>, [Rule; 3], Symbol) {
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "a").unwrap();
+        let empty = g.rule(None, &[]).unwrap();
+        let ab = g.literal_string(None, "ab").unwrap();
+        let start = g.rule(None, &[ab, empty, a]).unwrap();
+        g.set_start(start).unwrap();
+        let (a, empty, ab, start) = (a.rule(), g.symbol(empty), ab.rule(), start.rule());
+
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut t = p.run_recognizer(ByteScanner::new(Cursor::new("aba"))).unwrap();
+        let mut events = Events(vec![]);
+        walk(&mut events, t.next().unwrap());
+        (events.0, [a, ab, start], empty)
+    }
+
+    #[test]
+    fn walk_events() {
+        let (events, [a, ab, start], empty) = events(walk_buffered);
+        let expected = vec![
+            format!("enter {} 0..3", start),
+            format!("enter {} 0..2", ab),
+            "token 'a' 0..1".to_string(),
+            "token 'b' 1..2".to_string(),
+            format!("exit {}", ab),
+            format!("nulled {}", empty),
+            format!("enter {} 2..3", a),
+            "token 'a' 2..3".to_string(),
+            format!("exit {}", a),
+            format!("exit {}", start),
+        ];
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn stream_events() {
+        let (events, [a, ab, start], empty) = events(stream);
+        let expected = vec![
+            "token 'a' 0..1".to_string(),
+            "token 'b' 1..2".to_string(),
+            format!("rule {} 0..2", ab),
+            format!("nulled {}", empty),
+            "token 'a' 2..3".to_string(),
+            format!("rule {} 2..3", a),
+            format!("rule {} 0..3", start),
+        ];
+        assert_eq!(events, expected);
+    }
+}
@@ -12,7 +12,7 @@ impl Default for Config {
     fn default() -> Self {
         let internal: Marpa_Config = unsafe {
             let mut cfg: Marpa_Config = mem::zeroed();
-            assert!(marpa_c_init(&mut cfg) == MARPA_ERR_NONE as _);
+            assert!(marpa_c_init(&mut cfg) == MARPA_ERR_NONE as i32);
             cfg
         };
         Config { internal }
@@ -31,25 +31,27 @@ impl Drop for Grammar {
 
 impl Grammar {
     pub fn new() -> Result<Self> {
-        let mut cfg = Config::new();
-        unsafe {
-            let c_grammar = marpa_g_new(&mut cfg.internal());
-
-            cfg.error()?;
-
-            assert!(marpa_g_force_valued(c_grammar) >= 0);
-            Ok(Grammar { internal: c_grammar })
-        }
+        Grammar::with_config(Config::new())
     }
 
     pub fn with_config(cfg: Config) -> Result<Grammar> {
+        let g = Grammar::unforced(cfg)?;
+        assert!(unsafe { marpa_g_force_valued(g.internal) } >= 0);
+        Ok(g)
+    }
+
+    /// Create a grammar without forcing every symbol to be valued.
+    ///
+    /// New symbols start out unvalued, and steps for unvalued symbols and
+    /// rules are skipped during evaluation. Use `symbol_is_valued_set`, or
+    /// the `Value` methods of the same names, to choose which are valued.
+    pub fn unforced(cfg: Config) -> Result<Grammar> {
         let mut cfg = cfg;
         unsafe {
             let c_grammar = marpa_g_new(&mut cfg.internal());
 
             cfg.error()?;
 
-            assert!(marpa_g_force_valued(c_grammar) >= 0);
             Ok(Grammar { internal: c_grammar })
         }
     }
@@ -168,6 +170,26 @@ impl Grammar {
         }
     }
 
+    pub fn symbol_is_valued(&self, sym: Symbol) -> Result<bool> {
+        match unsafe { marpa_g_symbol_is_valued(self.internal, sym) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => err_nosym(),
+            -2 => self.error_or("error checking symbol valued status"),
+            _ => panic!("unexpected error code"),
+        }
+    }
+
+    pub fn symbol_is_valued_set(&mut self, sym: Symbol, valued: bool) -> Result<bool> {
+        match unsafe { marpa_g_symbol_is_valued_set(self.internal, sym, valued as i32) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => err_nosym(),
+            -2 => self.error_or("error setting symbol valued status"),
+            _ => panic!("unexpected error code"),
+        }
+    }
+
     pub fn new_rule(&mut self, lhs: Symbol, rhs: &[Symbol]) -> Result<Rule> {
         let rhs_ptr = rhs.as_ptr();
         let rhs_len = rhs.len() as i32;
@@ -346,6 +368,23 @@ impl Grammar {
         }
     }
 
+    pub fn symbol_rank_set(&mut self, sym: Symbol, rank: i32) -> Result<()> {
+        unsafe { marpa_g_symbol_rank_set(self.internal, sym, rank) };
+        self.error()
+    }
+
+    pub fn symbol_rank_get(&self, sym: Symbol) -> Result<i32> {
+        let rank = unsafe { marpa_g_symbol_rank(self.internal, sym) };
+        if rank == -2 {
+            match self.error() {
+                Ok(()) => Ok(-2),
+                Err(err) => Err(err),
+            }
+        } else {
+            Ok(rank)
+        }
+    }
+
     pub fn rule_null_high_set(&mut self, rule: Rule, high: bool) -> Result<()> {
         match unsafe { marpa_g_rule_null_high_set(self.internal, rule, high as i32) } {
             -1 => err_norule(),
@@ -355,7 +394,7 @@ impl Grammar {
         }
     }
 
-    pub fn rule_null_high(&mut self, rule: Rule) -> Result<bool> {
+    pub fn rule_null_high(&self, rule: Rule) -> Result<bool> {
         match unsafe { marpa_g_rule_null_high(self.internal, rule) } {
             -1 => err_norule(),
             -2 => self.error_or("error setting null high"),
@@ -386,7 +425,7 @@ impl Grammar {
         }
     }
 
-    pub fn symbol_is_completion_event(&mut self, sym: Symbol) -> Result<bool> {
+    pub fn symbol_is_completion_event(&self, sym: Symbol) -> Result<bool> {
         match unsafe { marpa_g_symbol_is_completion_event(self.internal, sym) } {
             -1 => err_nosym(),
             -2 => self.error_or("error getting completion event"),
@@ -406,7 +445,7 @@ impl Grammar {
         }
     }
 
-    pub fn symbol_is_nulled_event(&mut self, sym: Symbol) -> Result<bool> {
+    pub fn symbol_is_nulled_event(&self, sym: Symbol) -> Result<bool> {
         match unsafe { marpa_g_symbol_is_nulled_event(self.internal, sym) } {
             -1 => err_nosym(),
             -2 => self.error_or("error getting nulled event"),
@@ -426,7 +465,7 @@ impl Grammar {
         }
     }
 
-    pub fn symbol_is_prediction_event(&mut self, sym: Symbol) -> Result<bool> {
+    pub fn symbol_is_prediction_event(&self, sym: Symbol) -> Result<bool> {
         match unsafe { marpa_g_symbol_is_prediction_event(self.internal, sym) } {
             -1 => err_nosym(),
             -2 => self.error_or("error getting prediction event"),
@@ -26,6 +26,7 @@ pub use crate::thin::symbol::{SymIter, Symbol, TokValue};
 pub use crate::thin::tree::Tree;
 pub use crate::thin::value::Value;
 
+pub use crate::thin::earley::EarleySet;
 pub use crate::thin::event::{Event, EventIter};
 
 pub use crate::thin::step::Step;
@@ -102,6 +102,14 @@ impl Recognizer {
         Ok(unsafe { marpa_r_latest_earley_set(self.internal) })
     }
 
+    /// The number of Earley items in `set`.
+    pub fn earley_set_size(&self, set: EarleySet) -> Result<i32> {
+        match unsafe { _marpa_r_earley_set_size(self.internal, set) } {
+            -2 => self.grammar.error_or("error getting Earley set size"),
+            n => Ok(n),
+        }
+    }
+
     pub fn earley_set_value(&self, set: EarleySet) -> Result<char> {
         match unsafe { marpa_r_earley_set_value(self.internal, set) } {
             -2 => self.grammar.error_or("error getting set value"),
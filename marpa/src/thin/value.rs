@@ -1,5 +1,6 @@
+use crate::result::{err_norule, err_nosym};
 use crate::thin::tree;
-use crate::thin::{Grammar, Result, Step, Tree};
+use crate::thin::{EarleySet, Grammar, Result, Rule, Step, Symbol, Tree};
 use libmarpa_sys::*;
 
 pub struct Value {
@@ -18,6 +19,87 @@ impl Value {
             v => Ok(Value { internal: v, grammar }),
         }
     }
+
+    pub fn symbol_is_valued(&self, sym: Symbol) -> Result<bool> {
+        match unsafe { marpa_v_symbol_is_valued(self.internal, sym) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => err_nosym(),
+            -2 => self.grammar.error_or("error checking symbol valued status"),
+            _ => panic!("unexpected error code"),
+        }
+    }
+
+    /// Choose whether tokens and nulled instances of `sym` produce steps.
+    ///
+    /// Must be called before the first step.
+    pub fn symbol_is_valued_set(&mut self, sym: Symbol, valued: bool) -> Result<bool> {
+        match unsafe { marpa_v_symbol_is_valued_set(self.internal, sym, valued as i32) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => err_nosym(),
+            -2 => self.grammar.error_or("error setting symbol valued status"),
+            _ => panic!("unexpected error code"),
+        }
+    }
+
+    /// The RHS symbols of `rule`, whose values a step for it gathers.
+    pub fn rule_rhs(&self, rule: Rule) -> Result<Vec<Symbol>> {
+        self.grammar.rule_rhs(rule)
+    }
+
+    pub fn rule_is_sequence(&self, rule: Rule) -> Result<bool> {
+        self.grammar.rule_is_sequence(rule)
+    }
+
+    /// The separator of sequence `rule`, an error if it has none.
+    pub fn sequence_separator(&self, rule: Rule) -> Result<Symbol> {
+        self.grammar.sequence_separator(rule)
+    }
+
+    pub fn rule_is_valued(&self, rule: Rule) -> Result<bool> {
+        match unsafe { marpa_v_rule_is_valued(self.internal, rule) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => err_norule(),
+            -2 => self.grammar.error_or("error checking rule valued status"),
+            _ => panic!("unexpected error code"),
+        }
+    }
+
+    /// Choose whether `rule` produces steps. This is the valued status of
+    /// its LHS, so it applies to every rule with the same LHS.
+    ///
+    /// Must be called before the first step.
+    pub fn rule_is_valued_set(&mut self, rule: Rule, valued: bool) -> Result<bool> {
+        match unsafe { marpa_v_rule_is_valued_set(self.internal, rule, valued as i32) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            -1 => err_norule(),
+            -2 => self.grammar.error_or("error setting rule valued status"),
+            _ => panic!("unexpected error code"),
+        }
+    }
+
+    /// Make every symbol and rule valued.
+    pub fn valued_force(&mut self) -> Result<()> {
+        match unsafe { marpa_v_valued_force(self.internal) } {
+            -2 => self.grammar.error_or("error forcing valued status"),
+            _ => Ok(()),
+        }
+    }
+
+    /// The Earley sets where the input covered by the current step starts
+    /// and ends.
+    pub fn span(&self) -> (EarleySet, EarleySet) {
+        unsafe {
+            let start = match (*self.internal).t_step_type as _ {
+                MARPA_STEP_RULE => (*self.internal).t_rule_start_ys_id,
+                _ => (*self.internal).t_token_start_ys_id,
+            };
+            (start, (*self.internal).t_ys_id)
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -11,6 +11,7 @@ pub struct TreeBuilder {
     token_rules: HashSet<Rule>,
     discard_rules: HashSet<Rule>,
     rules: HashSet<Rule>,
+    lossless: bool,
 }
 
 impl TreeBuilder {
@@ -41,19 +42,34 @@ impl TreeBuilder {
     pub fn is_discard(&mut self, rule_id: Rule) -> bool {
         self.discard_rules.contains(&rule_id)
     }
+
+    /// In lossless mode, discarded rules are kept as `Node::Trivia` rather
+    /// than being replaced by `Node::Null`, so that the input can be
+    /// reconstructed from the tree.
+    pub fn lossless(&mut self, lossless: bool) {
+        self.lossless = lossless;
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        self.lossless
+    }
 }
 
 impl Processor for TreeBuilder {
     type Tree = Handle<ByteToken>;
     type Token = ByteToken;
 
-    fn proc_rule(&mut self, rule: Rule, children: &[Handle<ByteToken>]) -> Handle<ByteToken> {
+    fn proc_rule(&mut self, rule: Rule, children: Vec<Handle<ByteToken>>) -> Handle<ByteToken> {
         if self.is_token(rule) {
-            Node::token(rule, rollup_token(children)).into()
+            Node::token(rule, rollup_token(&children)).into()
         } else if self.is_rule(rule) {
-            Node::rule(rule, rollup_rule(children)).into()
+            Node::rule(rule, rollup_rule(&children)).into()
         } else if self.is_discard(rule) {
-            Node::Null(0).into()
+            if self.is_lossless() {
+                Node::trivia(rule, rollup_token(&children)).into()
+            } else {
+                Node::Null(0).into()
+            }
         } else {
             Node::tree(rule, children).into()
         }
@@ -79,6 +95,7 @@ fn rollup_token_rec(handles: &[Handle<ByteToken>], out: &mut Vec<u8>) {
     for child in handles.iter() {
         match *child.borrow() {
             Node::Leaf(tok) => out.push(*tok),
+            Node::Trivia(_, ref val) => out.extend_from_slice(val),
             Node::Null(_) => {}
             Node::Tree(_, ref chs) => rollup_token_rec(chs, out),
             Node::Rule(_, _) => panic!("cannot rollup Rule into Token - this is an internal bug."),
@@ -99,6 +116,7 @@ fn rollup_rule_rec(handles: &[Handle<ByteToken>], out: &mut Vec<Handle<ByteToken
         match *child.borrow() {
             Node::Token(_, _) => out.push(child.clone()),
             Node::Rule(_, _) => out.push(child.clone()),
+            Node::Trivia(_, _) => out.push(child.clone()),
             Node::Null(_) => {}
             Node::Tree(_, ref chs) => rollup_rule_rec(chs, out),
             Node::Leaf(_) => panic!("cannot rollup Leaf into Rule - this is an internal bug."),
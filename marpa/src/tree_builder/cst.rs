@@ -0,0 +1,518 @@
+use crate::lexer::token::Token;
+use crate::thin::Rule;
+use crate::thin::Symbol;
+use crate::tree_builder::tree::Handle;
+use crate::tree_builder::tree::Node;
+use std::fmt;
+use std::io;
+use std::ops::{Deref, Range};
+
+/// Index of a node within a `Cst`.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The kind of a `Cst` node. Mirrors the variants of `Node`, with token text
+/// and children stored in the arena rather than in the kind itself.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NodeKind<T> {
+    Rule(Rule),
+    Tree(Rule),
+    Token(Rule),
+    Leaf(T),
+    Null(Symbol),
+}
+
+#[derive(Debug)]
+struct NodeData<T> {
+    kind: NodeKind<T>,
+    parent: Option<NodeId>,
+    // position of this node within its parent's children
+    index: u32,
+    children: Range<u32>,
+    text: Range<u32>,
+    leading: Range<u32>,
+    trailing: Range<u32>,
+}
+
+#[derive(Debug)]
+struct TriviaData {
+    rule: Rule,
+    text: Range<u32>,
+}
+
+/// A piece of discarded input attached to a token of a lossless `Cst`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trivia<'a> {
+    pub rule: Rule,
+    pub text: &'a [u8],
+}
+
+// A node whose children are being pushed, and the ids of those pushed so
+// far.
+struct Frame<T: Token> {
+    handle: Handle<T>,
+    id: NodeId,
+    next: usize,
+    ids: Vec<NodeId>,
+}
+
+impl<T: Token> Frame<T> {
+    fn new(handle: Handle<T>, id: NodeId) -> Frame<T> {
+        Frame {
+            handle,
+            id,
+            next: 0,
+            ids: vec![],
+        }
+    }
+}
+
+// Bookkeeping for attaching trivia to tokens while freezing a tree.
+#[derive(Default)]
+struct Attach {
+    // first trivia piece not yet attached to anything
+    pending: u32,
+    last_token: Option<NodeId>,
+    // whether trivia may still be attached as trailing to `last_token`
+    trailing: bool,
+}
+
+/// An immutable concrete syntax tree stored in a flat arena.
+///
+/// Nodes are addressed by `NodeId` and navigated through `NodeRef`s. Unlike
+/// `Handle`, a `Cst` owns all of its nodes in a handful of vectors, so it is
+/// `Send + Sync` whenever the token type is.
+///
+/// `Node::Trivia` produced by a lossless `TreeBuilder` does not become a node
+/// of its own. Instead, each piece is attached to a neighboring token: trivia
+/// following a token up to and including the first piece containing a newline
+/// is trailing trivia of that token, and everything else is leading trivia of
+/// the next one. Trivia at the very end of the input trails the last token.
+#[derive(Debug)]
+pub struct Cst<T: Token> {
+    nodes: Vec<NodeData<T>>,
+    edges: Vec<NodeId>,
+    text: Vec<u8>,
+    trivia: Vec<TriviaData>,
+}
+
+impl<T: Token + Clone> Cst<T> {
+    pub fn new(root: &Handle<T>) -> Cst<T> {
+        let mut cst = Cst {
+            nodes: vec![],
+            edges: vec![],
+            text: vec![],
+            trivia: vec![],
+        };
+        let mut attach = Attach::default();
+        cst.push(root, &mut attach);
+
+        // whatever is left over goes to the last token, or to the root if
+        // there are no tokens at all.
+        let end = cst.trivia.len() as u32;
+        if attach.pending < end {
+            match attach.last_token {
+                Some(tok) => cst.nodes[tok.index()].trailing.end = end,
+                None if !cst.nodes.is_empty() => cst.nodes[0].leading = attach.pending..end,
+                None => {}
+            }
+        }
+        cst
+    }
+
+    // Pushes the nodes under `root` in pre-order, with a frame on `stack`
+    // for each rule whose children are being pushed, so deep trees don't
+    // use up the call stack.
+    fn push(&mut self, root: &Handle<T>, attach: &mut Attach) {
+        let mut stack = vec![];
+        if let Some(id) = self.node(root, None, attach) {
+            stack.push(Frame::new(root.clone(), id));
+        }
+        while let Some(frame) = stack.last_mut() {
+            let child = match *frame.handle.borrow() {
+                Node::Rule(_, ref chs) | Node::Tree(_, ref chs) => chs.get(frame.next).cloned(),
+                _ => None,
+            };
+            if let Some(child) = child {
+                frame.next += 1;
+                if let Some(id) = self.node(&child, Some(frame.id), attach) {
+                    frame.ids.push(id);
+                    stack.push(Frame::new(child, id));
+                }
+                continue;
+            }
+
+            let frame = stack.pop().unwrap();
+            for (i, child) in frame.ids.iter().enumerate() {
+                self.nodes[child.index()].index = i as u32;
+            }
+            let start = self.edges.len() as u32;
+            self.edges.extend(frame.ids);
+            self.nodes[frame.id.index()].children = start..self.edges.len() as u32;
+        }
+    }
+
+    // Pushes the node for `handle` without its children, or only its text if
+    // it's trivia.
+    fn node(&mut self, handle: &Handle<T>, parent: Option<NodeId>, attach: &mut Attach) -> Option<NodeId> {
+        let id = NodeId(self.nodes.len() as u32);
+        let node = handle.borrow();
+        let (kind, text) = match *node {
+            Node::Rule(rule, _) => (NodeKind::Rule(rule), 0..0),
+            Node::Tree(rule, _) => (NodeKind::Tree(rule), 0..0),
+            Node::Token(rule, ref val) => (NodeKind::Token(rule), self.push_text(val)),
+            Node::Leaf(ref tok) => (NodeKind::Leaf(tok.clone()), 0..0),
+            Node::Null(sym) => (NodeKind::Null(sym), 0..0),
+            Node::Trivia(rule, ref val) => {
+                let text = self.push_text(val);
+                self.trivia.push(TriviaData { rule, text });
+                let end = self.trivia.len() as u32;
+                if let (Some(tok), true) = (attach.last_token, attach.trailing) {
+                    self.nodes[tok.index()].trailing.end = end;
+                    attach.pending = end;
+                    attach.trailing = !val.contains(&b'\n');
+                }
+                return None;
+            }
+        };
+
+        let mut leading = 0..0;
+        if let NodeKind::Token(_) | NodeKind::Leaf(_) = kind {
+            leading = attach.pending..self.trivia.len() as u32;
+            attach.pending = leading.end;
+            attach.last_token = Some(id);
+            attach.trailing = true;
+        }
+
+        self.nodes.push(NodeData {
+            kind,
+            parent,
+            index: 0,
+            children: 0..0,
+            text,
+            trailing: leading.end..leading.end,
+            leading,
+        });
+        Some(id)
+    }
+
+    fn push_text(&mut self, val: &[u8]) -> Range<u32> {
+        let start = self.text.len() as u32;
+        self.text.extend_from_slice(val);
+        start..self.text.len() as u32
+    }
+}
+
+impl<'a, T: Token + Clone> From<&'a Handle<T>> for Cst<T> {
+    fn from(other: &'a Handle<T>) -> Cst<T> {
+        Cst::new(other)
+    }
+}
+
+impl<T: Token> Cst<T> {
+    pub fn root(&self) -> NodeRef<'_, T> {
+        self.get(NodeId(0))
+    }
+
+    pub fn get(&self, id: NodeId) -> NodeRef<'_, T> {
+        assert!(id.index() < self.nodes.len(), "node id out of bounds: {:?}", id);
+        NodeRef { cst: self, id }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Iterate over every node in the tree in pre-order.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeRef<'_, T>> {
+        (0..self.nodes.len() as u32).map(move |i| self.get(NodeId(i)))
+    }
+
+    fn data(&self, id: NodeId) -> &NodeData<T> {
+        &self.nodes[id.index()]
+    }
+
+    fn slice(&self, range: &Range<u32>) -> &[u8] {
+        &self.text[range.start as usize..range.end as usize]
+    }
+
+    fn trivia(&self, range: &Range<u32>) -> impl Iterator<Item = Trivia<'_>> {
+        self.trivia[range.start as usize..range.end as usize].iter().map(move |t| Trivia {
+            rule: t.rule,
+            text: self.slice(&t.text),
+        })
+    }
+}
+
+impl<T: Token + Deref<Target = u8>> Cst<T> {
+    /// Write the tree back out as bytes, including any trivia.
+    ///
+    /// For a tree built by a lossless `TreeBuilder` this reproduces the
+    /// original input exactly.
+    pub fn write_to<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        for node in self.nodes() {
+            for trivia in node.leading_trivia() {
+                out.write_all(trivia.text)?;
+            }
+            match *node.kind() {
+                NodeKind::Token(_) => out.write_all(node.text())?,
+                NodeKind::Leaf(ref tok) => out.write_all(&[**tok])?,
+                _ => {}
+            }
+            for trivia in node.trailing_trivia() {
+                out.write_all(trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.write_to(&mut out).expect("writing to a Vec cannot fail");
+        out
+    }
+}
+
+/// A borrowed view of a single node in a `Cst`.
+pub struct NodeRef<'a, T: Token> {
+    cst: &'a Cst<T>,
+    id: NodeId,
+}
+
+impl<'a, T: Token> Clone for NodeRef<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T: Token> Copy for NodeRef<'a, T> {}
+
+impl<'a, T: Token> fmt::Debug for NodeRef<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NodeRef").field("id", &self.id).field("kind", self.kind()).finish()
+    }
+}
+
+impl<'a, T: Token> PartialEq for NodeRef<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.cst, other.cst) && self.id == other.id
+    }
+}
+
+impl<'a, T: Token> NodeRef<'a, T> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn cst(&self) -> &'a Cst<T> {
+        self.cst
+    }
+
+    pub fn kind(&self) -> &'a NodeKind<T> {
+        &self.cst.data(self.id).kind
+    }
+
+    /// The rule that produced this node, if it was produced by one.
+    pub fn rule(&self) -> Option<Rule> {
+        match *self.kind() {
+            NodeKind::Rule(rule) | NodeKind::Tree(rule) | NodeKind::Token(rule) => Some(rule),
+            _ => None,
+        }
+    }
+
+    /// The text of a `Token` node. Empty for every other kind.
+    pub fn text(&self) -> &'a [u8] {
+        self.cst.slice(&self.cst.data(self.id).text)
+    }
+
+    /// Trivia preceding this node. Only tokens and leaves carry trivia, with
+    /// the exception of a root that covers no tokens at all.
+    pub fn leading_trivia(&self) -> impl Iterator<Item = Trivia<'a>> {
+        self.cst.trivia(&self.cst.data(self.id).leading)
+    }
+
+    pub fn trailing_trivia(&self) -> impl Iterator<Item = Trivia<'a>> {
+        self.cst.trivia(&self.cst.data(self.id).trailing)
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a, T>> {
+        self.cst.data(self.id).parent.map(|id| self.cst.get(id))
+    }
+
+    pub fn child_ids(&self) -> &'a [NodeId] {
+        let range = &self.cst.data(self.id).children;
+        &self.cst.edges[range.start as usize..range.end as usize]
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = NodeRef<'a, T>> + 'a {
+        let cst = self.cst;
+        self.child_ids().iter().map(move |id| cst.get(*id))
+    }
+
+    pub fn child(&self, index: usize) -> Option<NodeRef<'a, T>> {
+        self.child_ids().get(index).map(|id| self.cst.get(*id))
+    }
+
+    pub fn first_child(&self) -> Option<NodeRef<'a, T>> {
+        self.child_ids().first().map(|id| self.cst.get(*id))
+    }
+
+    pub fn last_child(&self) -> Option<NodeRef<'a, T>> {
+        self.child_ids().last().map(|id| self.cst.get(*id))
+    }
+
+    fn siblings(&self) -> &'a [NodeId] {
+        match self.cst.data(self.id).parent {
+            Some(parent) => self.cst.get(parent).child_ids(),
+            None => &[],
+        }
+    }
+
+    pub fn next_sibling(&self) -> Option<NodeRef<'a, T>> {
+        let index = self.cst.data(self.id).index as usize;
+        self.siblings().get(index + 1).map(|id| self.cst.get(*id))
+    }
+
+    pub fn prev_sibling(&self) -> Option<NodeRef<'a, T>> {
+        let index = self.cst.data(self.id).index as usize;
+        if index == 0 {
+            return None;
+        }
+        self.siblings().get(index - 1).map(|id| self.cst.get(*id))
+    }
+
+    pub fn ancestors(&self) -> impl Iterator<Item = NodeRef<'a, T>> {
+        let mut next = self.parent();
+        ::std::iter::from_fn(move || {
+            let current = next?;
+            next = current.parent();
+            Some(current)
+        })
+    }
+
+    /// Iterate over this node and all of its descendants in pre-order.
+    pub fn descendants(&self) -> impl Iterator<Item = NodeRef<'a, T>> {
+        let mut stack = vec![*self];
+        ::std::iter::from_fn(move || {
+            let current = stack.pop()?;
+            stack.extend(current.child_ids().iter().rev().map(|id| current.cst.get(*id)));
+            Some(current)
+        })
+    }
+}
+
+impl<'a, T: Token> fmt::Display for NodeRef<'a, T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.kind() {
+            NodeKind::Tree(rule) => {
+                write!(f, "Tree({},", rule)?;
+                for child in self.children() {
+                    write!(f, " {}", child)?;
+                }
+                write!(f, ")")
+            }
+            NodeKind::Rule(rule) => {
+                write!(f, "Rule({},", rule)?;
+                for child in self.children() {
+                    write!(f, " {}", child)?;
+                }
+                write!(f, ")")
+            }
+            NodeKind::Token(rule) => {
+                write!(f, "Token({}, ", rule)?;
+                match ::std::str::from_utf8(self.text()) {
+                    Ok(s) => write!(f, "\"{}\"", s)?,
+                    Err(_) => write!(f, "{:?}", self.text())?,
+                }
+                write!(f, ")")
+            }
+            NodeKind::Leaf(ref tok) => write!(f, "Leaf({})", tok),
+            NodeKind::Null(sym) => write!(f, "Null({})", sym),
+        }
+    }
+}
+
+impl<T: Token> fmt::Display for Cst<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.root())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cst, NodeId, NodeKind};
+    use crate::lexer::byte_scanner::ByteToken;
+    use crate::tree_builder::tree::{Handle, Node};
+
+    fn sample() -> Handle<ByteToken> {
+        let a: Handle<ByteToken> = Node::token(1, "a").into();
+        let b: Handle<ByteToken> = Node::token(1, "bc").into();
+        let null: Handle<ByteToken> = Node::null(7).into();
+        let inner: Handle<ByteToken> = Node::rule(2, vec![b, null]).into();
+        Node::rule(3, vec![a, inner]).into()
+    }
+
+    fn must_be_send_sync<T: Send + Sync>(_: &T) {}
+
+    #[test]
+    fn freeze_handle() {
+        let handle = sample();
+        let cst = Cst::new(&handle);
+        must_be_send_sync(&cst);
+
+        assert_eq!(cst.len(), 5);
+        assert_eq!(format!("{}", cst), format!("{}", handle));
+
+        let root = cst.root();
+        assert_eq!(*root.kind(), NodeKind::Rule(3));
+        assert!(root.parent().is_none());
+        assert_eq!(root.children().count(), 2);
+
+        let a = root.first_child().unwrap();
+        assert_eq!(a.text(), b"a");
+        assert_eq!(a.parent(), Some(root));
+        assert!(a.prev_sibling().is_none());
+
+        let inner = a.next_sibling().unwrap();
+        assert_eq!(inner, root.last_child().unwrap());
+        assert_eq!(inner.prev_sibling(), Some(a));
+        assert!(inner.next_sibling().is_none());
+
+        let b = inner.child(0).unwrap();
+        assert_eq!(b.text(), b"bc");
+        assert_eq!(b.ancestors().map(|n| n.id()).collect::<Vec<_>>(), vec![inner.id(), root.id()]);
+        assert_eq!(*b.next_sibling().unwrap().kind(), NodeKind::Null(7));
+
+        let order: Vec<_> = root.descendants().map(|n| n.id()).collect();
+        assert_eq!(order, cst.nodes().map(|n| n.id()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn deep_tree() {
+        let mut handle: Handle<ByteToken> = Node::token(1, "x").into();
+        for _ in 0..100_000 {
+            handle = Node::rule(2, vec![handle]).into();
+        }
+        let cst = Cst::new(&handle);
+        assert_eq!(cst.len(), 100_001);
+        assert_eq!(cst.to_bytes(), b"x");
+        let leaf = cst.get(NodeId(100_000));
+        assert_eq!(leaf.ancestors().count(), 100_000);
+    }
+}
@@ -1,5 +1,16 @@
 mod builder;
+mod cst;
+mod pretty;
+mod query;
+#[cfg(feature = "serde")]
+mod serialize;
 mod tree;
 
 pub use self::builder::TreeBuilder;
+pub use self::cst::{Cst, NodeId, NodeKind, NodeRef, Trivia};
+pub use self::pretty::Pretty;
+pub use self::query::{Query, QueryMatch};
+#[cfg(feature = "serde")]
+pub use self::serialize::Named;
 pub use self::tree::Handle;
+pub use self::tree::Node;
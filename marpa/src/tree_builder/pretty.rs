@@ -0,0 +1,248 @@
+use crate::grammar::Names;
+use crate::lexer::token::Token;
+use crate::thin::{Rule, Symbol};
+use crate::tree_builder::tree::{Handle, Node};
+use std::fmt::{self, Display, Write};
+
+/// An s-expression printer for trees built by `TreeBuilder`.
+///
+/// Rules print as `(name child ...)`, tokens as `(name "text")`, trivia as
+/// `(trivia name "text")`, nulled symbols as `(null name)` and leaves as
+/// themselves. Rules and symbols without a name print as `#id`.
+///
+/// By default, every child goes on its own line, indented under its parent.
+#[derive(Clone, Default)]
+pub struct Pretty<'a> {
+    names: Option<&'a Names>,
+    max_depth: Option<usize>,
+    elide_trees: bool,
+    spans: bool,
+    compact: bool,
+}
+
+impl<'a> Pretty<'a> {
+    pub fn new() -> Pretty<'a> {
+        Default::default()
+    }
+
+    /// Print rules and symbols by name.
+    pub fn names(&mut self, names: &'a Names) {
+        self.names = Some(names);
+    }
+
+    /// Print nodes deeper than `depth` as `...`. The root is at depth 0.
+    pub fn max_depth(&mut self, depth: Option<usize>) {
+        self.max_depth = depth;
+    }
+
+    /// Print the children of `Node::Tree` nodes in place of the node.
+    pub fn elide_trees(&mut self, elide: bool) {
+        self.elide_trees = elide;
+    }
+
+    /// Print the byte offsets covered by each node as `@start..end`.
+    pub fn spans(&mut self, spans: bool) {
+        self.spans = spans;
+    }
+
+    /// Print the whole tree on one line.
+    pub fn compact(&mut self, compact: bool) {
+        self.compact = compact;
+    }
+
+    pub fn print<T: Token + Display>(&self, tree: &Handle<T>) -> String {
+        let mut out = String::new();
+        self.write(&mut out, tree).expect("writing to a String can't fail");
+        out
+    }
+
+    pub fn write<T: Token + Display, W: Write>(&self, out: &mut W, tree: &Handle<T>) -> fmt::Result {
+        let mut printer = Printer { opts: self, out, pos: 0 };
+        let mut first = true;
+        printer.node(tree, 0, &mut first)
+    }
+}
+
+struct Printer<'a, 'b, W> {
+    opts: &'b Pretty<'a>,
+    out: &'b mut W,
+    pos: usize,
+}
+
+impl<'a, 'b, W: Write> Printer<'a, 'b, W> {
+    fn rule(&self, rule: Rule) -> String {
+        match self.opts.names.and_then(|n| n.rule(rule)) {
+            Some(name) => name.into(),
+            None => format!("#{}", rule),
+        }
+    }
+
+    fn symbol(&self, sym: Symbol) -> String {
+        match self.opts.names.and_then(|n| n.symbol(sym)) {
+            Some(name) => name.into(),
+            None => format!("#{}", sym),
+        }
+    }
+
+    fn span(&mut self, start: usize) -> fmt::Result {
+        if self.opts.spans {
+            write!(self.out, "@{}..{}", start, self.pos)?;
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &[u8]) -> fmt::Result {
+        match ::std::str::from_utf8(text) {
+            Ok(s) => write!(self.out, "{:?}", s),
+            Err(_) => write!(self.out, "{:?}", text),
+        }
+    }
+
+    // Starts a node, separating it from the previous one.
+    fn separate(&mut self, depth: usize, first: &mut bool) -> fmt::Result {
+        if !*first {
+            if self.opts.compact {
+                self.out.write_char(' ')?;
+            } else {
+                self.out.write_char('\n')?;
+                for _ in 0..depth {
+                    self.out.write_str("  ")?;
+                }
+            }
+        }
+        *first = false;
+        Ok(())
+    }
+
+    // TODO tco
+    fn node<T: Token + Display>(&mut self, handle: &Handle<T>, depth: usize, first: &mut bool) -> fmt::Result {
+        let start = self.pos;
+        match *handle.borrow() {
+            Node::Tree(_, ref children) if self.opts.elide_trees => {
+                for child in children {
+                    self.node(child, depth, first)?;
+                }
+            }
+            Node::Rule(rule, ref children) | Node::Tree(rule, ref children) => {
+                self.separate(depth, first)?;
+                let name = self.rule(rule);
+                write!(self.out, "({}", name)?;
+                let end = start + children.iter().map(len).sum::<usize>();
+                if self.opts.spans {
+                    write!(self.out, "@{}..{}", start, end)?;
+                }
+                if matches!(self.opts.max_depth, Some(max) if depth >= max) {
+                    if !children.is_empty() {
+                        self.out.write_str(" ...")?;
+                    }
+                    self.pos = end;
+                } else {
+                    let mut first = false;
+                    for child in children {
+                        self.node(child, depth + 1, &mut first)?;
+                    }
+                }
+                self.out.write_char(')')?;
+            }
+            Node::Token(rule, ref text) => {
+                self.separate(depth, first)?;
+                self.pos += text.len();
+                let name = self.rule(rule);
+                write!(self.out, "({}", name)?;
+                self.span(start)?;
+                self.out.write_char(' ')?;
+                self.text(text)?;
+                self.out.write_char(')')?;
+            }
+            Node::Trivia(rule, ref text) => {
+                self.separate(depth, first)?;
+                self.pos += text.len();
+                let name = self.rule(rule);
+                write!(self.out, "(trivia {}", name)?;
+                self.span(start)?;
+                self.out.write_char(' ')?;
+                self.text(text)?;
+                self.out.write_char(')')?;
+            }
+            Node::Leaf(ref tok) => {
+                self.separate(depth, first)?;
+                self.pos += 1;
+                write!(self.out, "{}", tok)?;
+                self.span(start)?;
+            }
+            Node::Null(sym) => {
+                self.separate(depth, first)?;
+                let name = self.symbol(sym);
+                write!(self.out, "(null {}", name)?;
+                self.span(start)?;
+                self.out.write_char(')')?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// The number of input bytes covered by a node.
+fn len<T: Token>(handle: &Handle<T>) -> usize {
+    match *handle.borrow() {
+        Node::Rule(_, ref children) | Node::Tree(_, ref children) => children.iter().map(len).sum(),
+        Node::Token(_, ref text) | Node::Trivia(_, ref text) => text.len(),
+        Node::Leaf(_) => 1,
+        Node::Null(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::byte_scanner::ByteToken;
+
+    fn sample() -> Handle<ByteToken> {
+        let a: Handle<ByteToken> = Node::token(1, "a").into();
+        let ws: Handle<ByteToken> = Node::trivia(4, " ").into();
+        let b: Handle<ByteToken> = Node::token(1, "bc").into();
+        let null: Handle<ByteToken> = Node::null(7).into();
+        let leaf: Handle<ByteToken> = Node::leaf(ByteToken::from((b'd' as Symbol, 0))).into();
+        let inner: Handle<ByteToken> = Node::tree(2, vec![b, null]).into();
+        let leaves: Handle<ByteToken> = Node::rule(5, vec![leaf]).into();
+        Node::rule(3, vec![a, ws, inner, leaves]).into()
+    }
+
+    #[test]
+    fn pretty() {
+        let tree = sample();
+        let mut names = Names::new();
+        names.set_rule(1, "word");
+        names.set_rule(3, "start");
+        names.set_symbol(7, "empty");
+
+        let mut pretty = Pretty::new();
+        assert_eq!(
+            pretty.print(&tree),
+            "(#3\n  (#1 \"a\")\n  (trivia #4 \" \")\n  (#2\n    (#1 \"bc\")\n    (null #7))\n  (#5\n    'd'))"
+        );
+
+        pretty.names(&names);
+        pretty.compact(true);
+        pretty.spans(true);
+        assert_eq!(
+            pretty.print(&tree),
+            "(start@0..5 (word@0..1 \"a\") (trivia #4@1..2 \" \") (#2@2..4 (word@2..4 \"bc\") (null empty@4..4)) (#5@4..5 'd'@4..5))"
+        );
+
+        pretty.elide_trees(true);
+        pretty.spans(false);
+        assert_eq!(
+            pretty.print(&tree),
+            "(start (word \"a\") (trivia #4 \" \") (word \"bc\") (null empty) (#5 'd'))"
+        );
+
+        pretty.elide_trees(false);
+        pretty.spans(true);
+        pretty.max_depth(Some(1));
+        assert_eq!(
+            pretty.print(&tree),
+            "(start@0..5 (word@0..1 \"a\") (trivia #4@1..2 \" \") (#2@2..4 ...) (#5@4..5 ...))"
+        );
+    }
+}
@@ -0,0 +1,651 @@
+//! A small query language for `Cst` trees, in the spirit of tree-sitter
+//! queries.
+//!
+//! A query is one or more patterns:
+//!
+//! ```text
+//! ; calls whose first argument is the identifier `print`
+//! (call . (ident) @fn (#eq? @fn "print")) @call
+//! ```
+//!
+//! * `(name child ...)` matches a node produced by a rule called `name`, or
+//!   by any rule whose LHS is called `name`, or a nulled symbol called `name`.
+//!   `(_ child ...)` matches any node.
+//! * The child patterns must match children of the node in order, but other
+//!   children may come between them. A `.` anchors the next pattern to the
+//!   first child, two patterns to adjacent children, or a final pattern to
+//!   the last child.
+//! * `"text"` matches a node whose text is exactly `text`, and `_` matches
+//!   any node.
+//! * `@name` after a pattern captures the node it matched.
+//! * `(#eq? @a "text")`, `(#eq? @a @b)`, `(#not-eq? ...)` and
+//!   `(#contains? @a "text")` compare the text of captured nodes. They can
+//!   go inside a pattern or right after it.
+//! * `;` starts a comment that runs to the end of the line.
+
+use crate::error::Error;
+use crate::grammar::Names;
+use crate::lexer::token::Token;
+use crate::result::Result;
+use crate::thin::{Rule, Symbol};
+use crate::tree_builder::cst::{NodeKind, NodeRef};
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::ops::Deref;
+use std::str::CharIndices;
+
+/// A compiled query, ready to run over any number of trees.
+#[derive(Debug)]
+pub struct Query {
+    patterns: Vec<TopLevel>,
+    captures: Vec<String>,
+}
+
+#[derive(Debug)]
+struct TopLevel {
+    parts: Vec<Part>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug)]
+struct Pattern {
+    kind: Kind,
+    // each child pattern, along with whether it's anchored to the one before
+    children: Vec<(bool, Pattern)>,
+    anchor_end: bool,
+    captures: Vec<usize>,
+}
+
+// A pattern without its children, which are the parts after it with it as
+// their parent. The parts of a top-level pattern are in pre-order.
+#[derive(Debug)]
+struct Part {
+    kind: Kind,
+    captures: Vec<usize>,
+    parent: usize,
+    // the part for the child pattern before this one
+    prev: Option<usize>,
+    // whether it must match the child right after `prev`'s, or the first
+    anchored: bool,
+    // whether it must match the last child
+    last: bool,
+    // whether it must match a node without children, for `(name .)`
+    childless: bool,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Any,
+    Named { rules: HashSet<Rule>, symbols: HashSet<Symbol> },
+    Text(Vec<u8>),
+}
+
+#[derive(Debug)]
+enum Arg {
+    Capture(usize),
+    Text(Vec<u8>),
+}
+
+#[derive(Debug)]
+enum Predicate {
+    Eq(usize, Arg),
+    NotEq(usize, Arg),
+    Contains(usize, Vec<u8>),
+}
+
+/// A node matched by one of the patterns of a `Query`.
+pub struct QueryMatch<'a, T: Token> {
+    query: &'a Query,
+    pattern: usize,
+    node: NodeRef<'a, T>,
+    captures: Vec<(usize, NodeRef<'a, T>)>,
+}
+
+impl<'a, T: Token> QueryMatch<'a, T> {
+    /// The index of the pattern that matched.
+    pub fn pattern(&self) -> usize {
+        self.pattern
+    }
+
+    pub fn node(&self) -> NodeRef<'a, T> {
+        self.node
+    }
+
+    /// The node captured as `@name`.
+    pub fn get(&self, name: &str) -> Option<NodeRef<'a, T>> {
+        self.captures().find(|&(n, _)| n == name).map(|(_, node)| node)
+    }
+
+    /// The captured nodes, in the order they were matched.
+    pub fn captures(&self) -> impl Iterator<Item = (&'a str, NodeRef<'a, T>)> + '_ {
+        let names = &self.query.captures;
+        self.captures.iter().map(move |&(i, node)| (names[i].as_str(), node))
+    }
+}
+
+type Captures<'a, T> = Vec<(usize, NodeRef<'a, T>)>;
+
+impl Query {
+    /// Compile a query, resolving rule and symbol names with `names`.
+    pub fn new(source: &str, names: &Names) -> Result<Query> {
+        let mut parser = QueryParser {
+            lexer: Lexer::new(source),
+            names,
+            captures: vec![],
+            predicates: vec![],
+        };
+        let mut patterns: Vec<TopLevel> = vec![];
+        while let Some(tok) = parser.lexer.peek()? {
+            let pattern = if *tok == Tok::Open {
+                parser.lexer.next()?;
+                // a predicate after a pattern applies to that pattern
+                if let Some(&Tok::Predicate(_)) = parser.lexer.peek()? {
+                    parser.predicate()?;
+                    match patterns.last_mut() {
+                        Some(top) => top.predicates.append(&mut parser.predicates),
+                        None => return Err(Error::from("query: predicate before any pattern".to_string())),
+                    }
+                    continue;
+                }
+                let mut pattern = parser.node()?;
+                parser.captures(&mut pattern)?;
+                pattern
+            } else {
+                parser.pattern()?
+            };
+            let predicates = ::std::mem::take(&mut parser.predicates);
+            let parts = flatten(pattern);
+            patterns.push(TopLevel { parts, predicates });
+        }
+        if patterns.is_empty() {
+            return Err(Error::from("query: no patterns".to_string()));
+        }
+        Ok(Query {
+            patterns,
+            captures: parser.captures,
+        })
+    }
+
+    /// The names of the captures, without the leading `@`.
+    pub fn capture_names(&self) -> &[String] {
+        &self.captures
+    }
+
+    /// Find the matches for every node under and including `root`, in
+    /// pre-order and then by pattern. Only the first way a pattern matches a
+    /// given node is reported.
+    pub fn matches<'a, T>(&'a self, root: NodeRef<'a, T>) -> Vec<QueryMatch<'a, T>>
+    where
+        T: Token + Deref<Target = u8>,
+    {
+        let mut matches = vec![];
+        for node in root.descendants() {
+            for (i, top) in self.patterns.iter().enumerate() {
+                let holds = |caps: &Captures<T>| top.predicates.iter().all(|pred| pred.holds(caps));
+                if let Some(captures) = match_parts(&top.parts, node, holds) {
+                    matches.push(QueryMatch {
+                        query: self,
+                        pattern: i,
+                        node,
+                        captures,
+                    });
+                }
+            }
+        }
+        matches
+    }
+}
+
+// Splits a pattern into its parts, in pre-order.
+fn flatten(pattern: Pattern) -> Vec<Part> {
+    let mut parts: Vec<Part> = vec![];
+    // the last part of each parent so far, to find the one before a child
+    let mut last_child: Vec<Option<usize>> = vec![];
+    let mut stack = vec![(pattern, 0, false, false)];
+    while let Some((pattern, parent, anchored, last)) = stack.pop() {
+        let Pattern {
+            kind,
+            children,
+            anchor_end,
+            captures,
+        } = pattern;
+        let id = parts.len();
+        let prev = if id == 0 { None } else { last_child[parent].replace(id) };
+        last_child.push(None);
+        parts.push(Part {
+            kind,
+            captures,
+            parent,
+            prev,
+            anchored,
+            last,
+            childless: anchor_end && children.is_empty(),
+        });
+        let count = children.len();
+        for (i, (anchored, child)) in children.into_iter().enumerate().rev() {
+            stack.push((child, id, anchored, anchor_end && i + 1 == count));
+        }
+    }
+    parts
+}
+
+// Matches `node` against the first of `parts`, and the rest against its
+// descendants, backtracking until the captures are accepted. Only one node
+// is kept per part, so this uses memory in proportion to the pattern.
+fn match_parts<'a, T, F>(parts: &[Part], node: NodeRef<'a, T>, accept: F) -> Option<Captures<'a, T>>
+where
+    T: Token + Deref<Target = u8>,
+    F: Fn(&Captures<'a, T>) -> bool,
+{
+    if !parts[0].matches(node) {
+        return None;
+    }
+    // the node matched by each part so far, and its index among its siblings
+    let mut nodes = vec![(node, 0)];
+    let mut from = 0;
+    loop {
+        let k = nodes.len();
+        if k == parts.len() {
+            let captures = parts.iter().zip(&nodes);
+            let captures = captures
+                .flat_map(|(part, &(node, _))| part.captures.iter().map(move |&c| (c, node)))
+                .collect();
+            if accept(&captures) {
+                return Some(captures);
+            }
+        } else {
+            let part = &parts[k];
+            let parent = nodes[part.parent].0;
+            let len = parent.child_ids().len();
+            let start = part.prev.map_or(0, |prev| nodes[prev].1 + 1);
+            let end = if part.anchored { len.min(start + 1) } else { len };
+            let found = (from.max(start)..end).find(|&i| (!part.last || i + 1 == len) && part.matches(parent.child(i).unwrap()));
+            if let Some(i) = found {
+                nodes.push((parent.child(i).unwrap(), i));
+                from = 0;
+                continue;
+            }
+        }
+        // try the next child for the last part matched
+        if nodes.len() == 1 {
+            return None;
+        }
+        from = nodes.pop().unwrap().1 + 1;
+    }
+}
+
+impl Part {
+    fn matches<T: Token + Deref<Target = u8>>(&self, node: NodeRef<T>) -> bool {
+        let kind_matches = match self.kind {
+            Kind::Any => true,
+            Kind::Text(ref text) => node_text(node) == *text,
+            Kind::Named { ref rules, ref symbols } => match *node.kind() {
+                NodeKind::Rule(rule) | NodeKind::Tree(rule) | NodeKind::Token(rule) => rules.contains(&rule),
+                NodeKind::Leaf(ref tok) => symbols.contains(&tok.sym()),
+                NodeKind::Null(sym) => symbols.contains(&sym),
+            },
+        };
+        kind_matches && (!self.childless || node.child_ids().is_empty())
+    }
+}
+
+// The text of a node's tokens, without trivia.
+fn node_text<T: Token + Deref<Target = u8>>(node: NodeRef<T>) -> Vec<u8> {
+    let mut text = vec![];
+    for node in node.descendants() {
+        match *node.kind() {
+            NodeKind::Token(_) => text.extend_from_slice(node.text()),
+            NodeKind::Leaf(ref tok) => text.push(**tok),
+            _ => {}
+        }
+    }
+    text
+}
+
+impl Predicate {
+    fn holds<T: Token + Deref<Target = u8>>(&self, caps: &Captures<T>) -> bool {
+        let text = |c: usize| caps.iter().find(|&&(i, _)| i == c).map(|&(_, node)| node_text(node));
+        let arg = |arg: &Arg| match *arg {
+            Arg::Capture(c) => text(c),
+            Arg::Text(ref t) => Some(t.clone()),
+        };
+        match *self {
+            Predicate::Eq(c, ref other) => text(c).is_some() && text(c) == arg(other),
+            Predicate::NotEq(c, ref other) => text(c).is_some() && text(c) != arg(other),
+            Predicate::Contains(c, ref needle) => match text(c) {
+                Some(t) => needle.is_empty() || t.windows(needle.len()).any(|w| w == &needle[..]),
+                None => false,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Open,
+    Close,
+    Dot,
+    Wildcard,
+    Name(String),
+    Capture(String),
+    Predicate(String),
+    Text(String),
+}
+
+struct Lexer<'s> {
+    chars: Peekable<CharIndices<'s>>,
+    peeked: Option<Tok>,
+}
+
+impl<'s> Lexer<'s> {
+    fn new(source: &'s str) -> Self {
+        Lexer {
+            chars: source.char_indices().peekable(),
+            peeked: None,
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Tok>> {
+        if self.peeked.is_none() {
+            self.peeked = self.lex()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<Tok>> {
+        self.peek()?;
+        Ok(self.peeked.take())
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '-' || c == '?') {
+                break;
+            }
+            word.push(c);
+            self.chars.next();
+        }
+        word
+    }
+
+    fn lex(&mut self) -> Result<Option<Tok>> {
+        loop {
+            let (pos, c) = match self.chars.next() {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+            return Ok(Some(match c {
+                c if c.is_whitespace() => continue,
+                ';' => {
+                    while matches!(self.chars.peek(), Some(&(_, c)) if c != '\n') {
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                '(' => Tok::Open,
+                ')' => Tok::Close,
+                '.' => Tok::Dot,
+                '@' | '#' => {
+                    let word = self.word();
+                    if word.is_empty() {
+                        return Err(Error::from(format!("query: expected a name after `{}` at {}", c, pos)));
+                    }
+                    if c == '@' {
+                        Tok::Capture(word)
+                    } else {
+                        Tok::Predicate(word)
+                    }
+                }
+                '"' => {
+                    let unterminated = || Error::from(format!("query: unterminated string at {}", pos));
+                    let mut text = String::new();
+                    loop {
+                        match self.chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) => match self.chars.next() {
+                                Some((_, 'n')) => text.push('\n'),
+                                Some((_, 't')) => text.push('\t'),
+                                Some((_, c)) => text.push(c),
+                                None => return Err(unterminated()),
+                            },
+                            Some((_, c)) => text.push(c),
+                            None => return Err(unterminated()),
+                        }
+                    }
+                    Tok::Text(text)
+                }
+                c if c.is_alphanumeric() || c == '_' => {
+                    let mut word = c.to_string();
+                    word.push_str(&self.word());
+                    if word == "_" {
+                        Tok::Wildcard
+                    } else {
+                        Tok::Name(word)
+                    }
+                }
+                c => return Err(Error::from(format!("query: unexpected `{}` at {}", c, pos))),
+            }));
+        }
+    }
+}
+
+struct QueryParser<'s, 'n> {
+    lexer: Lexer<'s>,
+    names: &'n Names,
+    captures: Vec<String>,
+    predicates: Vec<Predicate>,
+}
+
+impl<'s, 'n> QueryParser<'s, 'n> {
+    fn expect_next(&mut self) -> Result<Tok> {
+        match self.lexer.next()? {
+            Some(tok) => Ok(tok),
+            None => Err(Error::from("query: unexpected end of input".to_string())),
+        }
+    }
+
+    fn capture(&mut self, name: String) -> usize {
+        match self.captures.iter().position(|c| *c == name) {
+            Some(i) => i,
+            None => {
+                self.captures.push(name);
+                self.captures.len() - 1
+            }
+        }
+    }
+
+    fn named(&self, name: &str) -> Result<Kind> {
+        let rules: HashSet<_> = self.names.rules_named(name).collect();
+        let symbols: HashSet<_> = self.names.symbols_named(name).collect();
+        if rules.is_empty() && symbols.is_empty() {
+            return Err(Error::from(format!("query: unknown name `{}`", name)));
+        }
+        Ok(Kind::Named { rules, symbols })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern> {
+        let mut pattern = match self.expect_next()? {
+            Tok::Wildcard => Pattern::new(Kind::Any),
+            Tok::Text(text) => Pattern::new(Kind::Text(text.into_bytes())),
+            Tok::Name(name) => Pattern::new(self.named(&name)?),
+            Tok::Open => self.node()?,
+            tok => return Err(Error::from(format!("query: expected a pattern, found {:?}", tok))),
+        };
+        self.captures(&mut pattern)?;
+        Ok(pattern)
+    }
+
+    fn captures(&mut self, pattern: &mut Pattern) -> Result<()> {
+        while let Some(&Tok::Capture(_)) = self.lexer.peek()? {
+            if let Some(Tok::Capture(name)) = self.lexer.next()? {
+                let capture = self.capture(name);
+                pattern.captures.push(capture);
+            }
+        }
+        Ok(())
+    }
+
+    // The rest of a node pattern, after its `(`.
+    fn node(&mut self) -> Result<Pattern> {
+        let mut pattern = match self.expect_next()? {
+            Tok::Wildcard => Pattern::new(Kind::Any),
+            Tok::Name(name) => Pattern::new(self.named(&name)?),
+            tok => return Err(Error::from(format!("query: expected a name, found {:?}", tok))),
+        };
+        let mut anchored = false;
+        loop {
+            match self.lexer.peek()? {
+                Some(&Tok::Close) => {
+                    self.lexer.next()?;
+                    pattern.anchor_end = anchored;
+                    return Ok(pattern);
+                }
+                Some(&Tok::Dot) => {
+                    self.lexer.next()?;
+                    anchored = true;
+                }
+                Some(&Tok::Open) => {
+                    self.lexer.next()?;
+                    if let Some(&Tok::Predicate(_)) = self.lexer.peek()? {
+                        self.predicate()?;
+                        continue;
+                    }
+                    let mut child = self.node()?;
+                    self.captures(&mut child)?;
+                    pattern.children.push((anchored, child));
+                    anchored = false;
+                }
+                _ => {
+                    let child = self.pattern()?;
+                    pattern.children.push((anchored, child));
+                    anchored = false;
+                }
+            }
+        }
+    }
+
+    // A predicate, after its `(`.
+    fn predicate(&mut self) -> Result<()> {
+        let name = match self.expect_next()? {
+            Tok::Predicate(name) => name,
+            _ => unreachable!(),
+        };
+        let subject = match self.expect_next()? {
+            Tok::Capture(c) => self.capture(c),
+            tok => return Err(Error::from(format!("query: #{} expects a capture, found {:?}", name, tok))),
+        };
+        let arg = match self.expect_next()? {
+            Tok::Capture(c) => Arg::Capture(self.capture(c)),
+            Tok::Text(text) => Arg::Text(text.into_bytes()),
+            tok => return Err(Error::from(format!("query: #{} expects a capture or string, found {:?}", name, tok))),
+        };
+        if self.expect_next()? != Tok::Close {
+            return Err(Error::from(format!("query: too many arguments to #{}", name)));
+        }
+        self.predicates.push(match (name.as_str(), arg) {
+            ("eq?", arg) => Predicate::Eq(subject, arg),
+            ("not-eq?", arg) => Predicate::NotEq(subject, arg),
+            ("contains?", Arg::Text(text)) => Predicate::Contains(subject, text),
+            ("contains?", _) => return Err(Error::from("query: #contains? expects a string".to_string())),
+            _ => return Err(Error::from(format!("query: unknown predicate #{}", name))),
+        });
+        Ok(())
+    }
+}
+
+impl Pattern {
+    fn new(kind: Kind) -> Pattern {
+        Pattern {
+            kind,
+            children: vec![],
+            anchor_end: false,
+            captures: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::byte_scanner::ByteToken;
+    use crate::tree_builder::cst::Cst;
+    use crate::tree_builder::tree::{Handle, Node};
+
+    // call ::= ident args
+    fn call(name: &str, args: &[&str]) -> Handle<ByteToken> {
+        let ident: Handle<ByteToken> = Node::token(1, name).into();
+        let args: Vec<Handle<ByteToken>> = args.iter().map(|a| Node::token(1, *a).into()).collect();
+        let args: Handle<ByteToken> = Node::rule(2, args).into();
+        Node::rule(3, vec![ident, args]).into()
+    }
+
+    fn names() -> Names {
+        let mut names = Names::new();
+        names.set_symbol(300, "ident");
+        names.set_lhs(1, 300);
+        names.set_rule(2, "args");
+        names.set_lhs(2, 301);
+        names.set_symbol(302, "call");
+        names.set_lhs(3, 302);
+        names.set_rule(4, "program");
+        names.set_lhs(4, 303);
+        names
+    }
+
+    #[test]
+    fn query() {
+        let calls = vec![call("print", &["x"]), call("len", &["print"]), call("print", &["a", "b"])];
+        let tree: Handle<ByteToken> = Node::rule(4, calls).into();
+        let cst = Cst::new(&tree);
+        let names = names();
+
+        let q = Query::new(r#"(call . (ident) @fn (#eq? @fn "print")) @call"#, &names).unwrap();
+        let found: Vec<_> = q.matches(cst.root()).iter().map(|m| node_text(m.get("call").unwrap())).collect();
+        assert_eq!(found, vec![b"printx".to_vec(), b"printab".to_vec()]);
+
+        // the first child of `call` is anchored, so `print` as an argument isn't a match
+        let q = Query::new(r#"(call . "print")"#, &names).unwrap();
+        assert_eq!(q.matches(cst.root()).len(), 2);
+        let q = Query::new(r#"(call (args "print"))"#, &names).unwrap();
+        assert_eq!(q.matches(cst.root()).len(), 1);
+
+        let q = Query::new("(args (ident) @a . (ident) @b .) ; adjacent\n(_ _ .)", &names).unwrap();
+        let matches = q.matches(cst.root());
+        let first: Vec<_> = matches.iter().filter(|m| m.pattern() == 0).collect();
+        assert_eq!(first.len(), 1);
+        assert_eq!(node_text(first[0].get("a").unwrap()), b"a");
+        assert_eq!(node_text(first[0].get("b").unwrap()), b"b");
+        assert_eq!(q.capture_names(), &["a".to_string(), "b".to_string()]);
+
+        let q = Query::new(r#"(ident) @i (#contains? @i "in")"#, &names).unwrap();
+        assert_eq!(q.matches(cst.root()).len(), 3);
+
+        assert!(Query::new("(nope)", &names).is_err());
+        assert!(Query::new("(call", &names).is_err());
+        for unterminated in &["(call (ident) @x (#eq? @x \"y))", "(call (ident) @x (#eq? @x \"y\\"] {
+            let err = Query::new(unterminated, &names).err().unwrap().to_string();
+            assert!(err.contains("unterminated string"), "{}", err);
+        }
+        assert!(Query::new("(#eq? @x \"y\") (call)", &names).is_err());
+        assert!(Query::new("(call (#bogus? @x \"y\"))", &names).is_err());
+    }
+
+    #[test]
+    fn deep_tree() {
+        // args ::= args | ident, 100000 deep
+        let mut tree: Handle<ByteToken> = Node::token(1, "x").into();
+        for _ in 0..100_000 {
+            tree = Node::rule(2, vec![tree]).into();
+        }
+        let cst = Cst::new(&tree);
+        let names = names();
+
+        let q = Query::new("(args . (args . (ident) @x .) .)", &names).unwrap();
+        let matches = q.matches(cst.root());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].get("x").unwrap().text(), b"x");
+        let q = Query::new("(args (args))", &names).unwrap();
+        assert_eq!(q.matches(cst.root()).len(), 99_999);
+    }
+}
@@ -0,0 +1,433 @@
+//! Serde support for parse trees, enabled by the `serde` feature.
+//!
+//! `Handle` and `Cst` trees serialize to the same nested shape. Every node is
+//! a map with a `kind` (`rule`, `tree`, `token`, `trivia`, `leaf` or `null`),
+//! its `rule` or `symbol` id, an optional `name`, and a `span` of byte
+//! offsets into the text of the tree. Rules and trees have `children`, and
+//! tokens, trivia and leaves have `text`, which is a string if it's valid
+//! UTF-8 and an array of bytes otherwise. Wrap a tree in `Named` to include
+//! names.
+//!
+//! Spans are offsets into the input only for trees built by a lossless
+//! `TreeBuilder`. Otherwise discarded input is missing from the tree, and
+//! every span after it is short by its length.
+//!
+//! A `Cst` can be deserialized from this shape, which makes it possible to
+//! ship parse results between processes or keep them as golden files.
+//! Trivia attached to a token is written next to it, so a lossless `Cst`
+//! survives the round trip.
+//!
+//! Serde works on nested data by recursion, so converting a tree goes one
+//! call deeper for every level of it, and how deep a tree can be serialized
+//! depends on the stack. Formats may limit the nesting they read back:
+//! `serde_json` gives up past 128 levels, which holds a tree 62 levels deep,
+//! since every level is a map and an array of children.
+
+use crate::grammar::Names;
+use crate::lexer::byte_scanner::ByteToken;
+use crate::lexer::token::Token;
+use crate::thin::{Rule, Symbol};
+use crate::tree_builder::cst::{Cst, NodeKind, NodeRef};
+use crate::tree_builder::tree::{Handle, Node};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A tree along with the names of its rules and symbols.
+pub struct Named<'a, T: ?Sized> {
+    tree: &'a T,
+    names: &'a Names,
+}
+
+impl<'a, T: ?Sized> Named<'a, T> {
+    pub fn new(tree: &'a T, names: &'a Names) -> Self {
+        Named { tree, names }
+    }
+}
+
+type Span = (usize, usize);
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum SerNode {
+    Rule {
+        rule: Rule,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        span: Span,
+        children: Vec<SerNode>,
+    },
+    Tree {
+        rule: Rule,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        span: Span,
+        children: Vec<SerNode>,
+    },
+    Token {
+        rule: Rule,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        span: Span,
+        text: Text,
+    },
+    Trivia {
+        rule: Rule,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        span: Span,
+        text: Text,
+    },
+    Leaf {
+        symbol: Symbol,
+        span: Span,
+        text: Text,
+    },
+    Null {
+        symbol: Symbol,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        span: Span,
+    },
+}
+
+struct Text(Vec<u8>);
+
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match ::std::str::from_utf8(&self.0) {
+            Ok(s) => serializer.serialize_str(s),
+            Err(_) => serializer.serialize_bytes(&self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Text, D::Error> {
+        struct TextVisitor;
+
+        impl<'de> Visitor<'de> for TextVisitor {
+            type Value = Text;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a string or an array of bytes")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Text, E> {
+                Ok(Text(v.as_bytes().to_vec()))
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Text, E> {
+                Ok(Text(v.to_vec()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Text, A::Error> {
+                let mut bytes = vec![];
+                while let Some(b) = seq.next_element()? {
+                    bytes.push(b);
+                }
+                Ok(Text(bytes))
+            }
+        }
+
+        deserializer.deserialize_any(TextVisitor)
+    }
+}
+
+// Tracks the position in the text of the tree while converting it.
+struct Convert<'a> {
+    names: Option<&'a Names>,
+    pos: usize,
+}
+
+impl<'a> Convert<'a> {
+    fn rule_name(&self, rule: Rule) -> Option<String> {
+        self.names.and_then(|n| n.rule(rule)).map(String::from)
+    }
+
+    fn symbol_name(&self, sym: Symbol) -> Option<String> {
+        self.names.and_then(|n| n.symbol(sym)).map(String::from)
+    }
+
+    fn text(&mut self, text: &[u8]) -> (Span, Text) {
+        let start = self.pos;
+        self.pos += text.len();
+        ((start, self.pos), Text(text.to_vec()))
+    }
+
+    // Recurses once per level of the tree, as serde does when serializing
+    // the result.
+    fn handle(&mut self, handle: &Handle<ByteToken>) -> SerNode {
+        let start = self.pos;
+        match *handle.borrow() {
+            Node::Rule(rule, ref chs) => {
+                let children = chs.iter().map(|ch| self.handle(ch)).collect();
+                let (name, span) = (self.rule_name(rule), (start, self.pos));
+                SerNode::Rule { rule, name, span, children }
+            }
+            Node::Tree(rule, ref chs) => {
+                let children = chs.iter().map(|ch| self.handle(ch)).collect();
+                let (name, span) = (self.rule_name(rule), (start, self.pos));
+                SerNode::Tree { rule, name, span, children }
+            }
+            Node::Token(rule, ref val) => {
+                let (span, text) = self.text(val);
+                let name = self.rule_name(rule);
+                SerNode::Token { rule, name, span, text }
+            }
+            Node::Trivia(rule, ref val) => {
+                let (span, text) = self.text(val);
+                let name = self.rule_name(rule);
+                SerNode::Trivia { rule, name, span, text }
+            }
+            Node::Leaf(tok) => {
+                let (span, text) = self.text(&[*tok]);
+                SerNode::Leaf {
+                    symbol: tok.sym(),
+                    span,
+                    text,
+                }
+            }
+            Node::Null(symbol) => SerNode::Null {
+                symbol,
+                name: self.symbol_name(symbol),
+                span: (start, start),
+            },
+        }
+    }
+
+    // Pushes the node along with any trivia attached to it.
+    fn cst(&mut self, node: NodeRef<ByteToken>, out: &mut Vec<SerNode>) {
+        for trivia in node.leading_trivia() {
+            let (span, text) = self.text(trivia.text);
+            let (rule, name) = (trivia.rule, self.rule_name(trivia.rule));
+            out.push(SerNode::Trivia { rule, name, span, text });
+        }
+
+        let start = self.pos;
+        let mut children = vec![];
+        for child in node.children() {
+            self.cst(child, &mut children);
+        }
+        out.push(match *node.kind() {
+            NodeKind::Rule(rule) => SerNode::Rule {
+                rule,
+                name: self.rule_name(rule),
+                span: (start, self.pos),
+                children,
+            },
+            NodeKind::Tree(rule) => SerNode::Tree {
+                rule,
+                name: self.rule_name(rule),
+                span: (start, self.pos),
+                children,
+            },
+            NodeKind::Token(rule) => {
+                let (span, text) = self.text(node.text());
+                let name = self.rule_name(rule);
+                SerNode::Token { rule, name, span, text }
+            }
+            NodeKind::Leaf(tok) => {
+                let (span, text) = self.text(&[*tok]);
+                SerNode::Leaf {
+                    symbol: tok.sym(),
+                    span,
+                    text,
+                }
+            }
+            NodeKind::Null(symbol) => SerNode::Null {
+                symbol,
+                name: self.symbol_name(symbol),
+                span: (start, start),
+            },
+        });
+
+        for trivia in node.trailing_trivia() {
+            let (span, text) = self.text(trivia.text);
+            let (rule, name) = (trivia.rule, self.rule_name(trivia.rule));
+            out.push(SerNode::Trivia { rule, name, span, text });
+        }
+    }
+
+    fn cst_root(&mut self, cst: &Cst<ByteToken>) -> SerNode {
+        let mut out = vec![];
+        self.cst(cst.root(), &mut out);
+        // a root without tokens may carry trivia of its own
+        match out.iter().position(|n| !matches!(*n, SerNode::Trivia { .. })) {
+            Some(root) if out.len() > 1 => {
+                let mut root = out.remove(root);
+                match root {
+                    SerNode::Rule { ref mut children, .. } | SerNode::Tree { ref mut children, .. } => {
+                        *children = out.into_iter().chain(::std::mem::take(children)).collect()
+                    }
+                    _ => {}
+                }
+                root
+            }
+            Some(root) => out.remove(root),
+            None => unreachable!("a Cst always has a root"),
+        }
+    }
+}
+
+// Recurses once per level of the tree, within the nesting allowed by the
+// format it was read from.
+fn to_handle(node: SerNode) -> Handle<ByteToken> {
+    let children = |chs: Vec<SerNode>| chs.into_iter().map(to_handle).collect::<Vec<_>>();
+    match node {
+        SerNode::Rule { rule, children: chs, .. } => Node::rule(rule, children(chs)).into(),
+        SerNode::Tree { rule, children: chs, .. } => Node::tree(rule, children(chs)).into(),
+        SerNode::Token { rule, text, .. } => Node::token(rule, text.0).into(),
+        SerNode::Trivia { rule, text, .. } => Node::trivia(rule, text.0).into(),
+        SerNode::Leaf { symbol, .. } => Node::leaf(ByteToken::from((symbol, 0))).into(),
+        SerNode::Null { symbol, .. } => Node::null(symbol).into(),
+    }
+}
+
+impl Serialize for Handle<ByteToken> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Convert { names: None, pos: 0 }.handle(self).serialize(serializer)
+    }
+}
+
+impl<'a> Serialize for Named<'a, Handle<ByteToken>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut convert = Convert {
+            names: Some(self.names),
+            pos: 0,
+        };
+        convert.handle(self.tree).serialize(serializer)
+    }
+}
+
+impl Serialize for Cst<ByteToken> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Convert { names: None, pos: 0 }.cst_root(self).serialize(serializer)
+    }
+}
+
+impl<'a> Serialize for Named<'a, Cst<ByteToken>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut convert = Convert {
+            names: Some(self.names),
+            pos: 0,
+        };
+        convert.cst_root(self.tree).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cst<ByteToken> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Cst<ByteToken>, D::Error> {
+        let root = SerNode::deserialize(deserializer)?;
+        Ok(Cst::new(&to_handle(root)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Named;
+    use crate::grammar::Names;
+    use crate::lexer::byte_scanner::ByteToken;
+    use crate::tree_builder::cst::Cst;
+    use crate::tree_builder::tree::{Handle, Node};
+
+    // A lossless tree of `a ::= 'x'` and `b ::= 'y'`, with spaces between
+    // them discarded.
+    fn parsed(input: &str) -> Cst<ByteToken> {
+        use crate::grammar::Grammar;
+        use crate::lexer::byte_scanner::ByteScanner;
+        use crate::parser::Parser;
+        use crate::stack::proc_value;
+        use crate::tree_builder::TreeBuilder;
+        use std::io::Cursor;
+
+        let mut g = Grammar::new().unwrap();
+        let a = g.literal_string(None, "x").unwrap();
+        let b = g.literal_string(None, "y").unwrap();
+        let space = g.literal_string(None, " ").unwrap();
+        let ws = g.star(None, space).unwrap();
+        let start = g.rule(None, &[ws, a, ws, b]).unwrap();
+        g.set_start(start).unwrap();
+        let mut builder = TreeBuilder::new();
+        builder.lossless(true);
+        builder.rule(start.rule());
+        builder.token(a.rule());
+        builder.token(b.rule());
+        builder.discard(ws.rule());
+        let mut p = Parser::with_grammar(g.unwrap());
+        let mut t = p.run_recognizer(ByteScanner::new(Cursor::new(input))).unwrap();
+        Cst::new(&proc_value(builder, t.next().unwrap()))
+    }
+
+    #[test]
+    fn input_spans() {
+        let json: serde_json::Value = serde_json::to_value(parsed("  x   y")).unwrap();
+        let spans: Vec<_> = json["children"].as_array().unwrap().iter().map(|ch| ch["span"].clone()).collect();
+        assert_eq!(
+            spans,
+            vec![
+                serde_json::json!([0, 2]),
+                serde_json::json!([2, 3]),
+                serde_json::json!([3, 6]),
+                serde_json::json!([6, 7])
+            ]
+        );
+    }
+
+    fn sample() -> Handle<ByteToken> {
+        let a: Handle<ByteToken> = Node::token(1, "a").into();
+        let ws: Handle<ByteToken> = Node::trivia(4, " \n").into();
+        let b: Handle<ByteToken> = Node::token(1, "bc").into();
+        let null: Handle<ByteToken> = Node::null(7).into();
+        let inner: Handle<ByteToken> = Node::rule(2, vec![b, null]).into();
+        Node::rule(3, vec![a, ws, inner]).into()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut names = Names::new();
+        names.set_rule(1, "word");
+        names.set_rule(3, "start");
+        names.set_symbol(7, "empty");
+
+        let handle = sample();
+        let expected = r#"{"kind":"rule","rule":3,"name":"start","span":[0,5],"children":[{"kind":"token","rule":1,"name":"word","span":[0,1],"text":"a"},{"kind":"trivia","rule":4,"span":[1,3],"text":" \n"},{"kind":"rule","rule":2,"span":[3,5],"children":[{"kind":"token","rule":1,"name":"word","span":[3,5],"text":"bc"},{"kind":"null","symbol":7,"name":"empty","span":[5,5]}]}]}"#;
+        assert_eq!(serde_json::to_string(&Named::new(&handle, &names)).unwrap(), expected);
+
+        let cst = Cst::new(&handle);
+        assert_eq!(serde_json::to_string(&Named::new(&cst, &names)).unwrap(), expected);
+
+        let json = serde_json::to_string(&cst).unwrap();
+        let back: Cst<ByteToken> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.to_bytes(), b"a \nbc");
+        assert_eq!(format!("{}", back), format!("{}", cst));
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn binary_text() {
+        let handle: Handle<ByteToken> = Node::token(1, vec![0xff, b'a']).into();
+        let json = serde_json::to_string(&handle).unwrap();
+        assert_eq!(json, r#"{"kind":"token","rule":1,"span":[0,2],"text":[255,97]}"#);
+        let back: Cst<ByteToken> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.root().text(), &[0xff, b'a']);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let deep = |depth| {
+            let mut handle: Handle<ByteToken> = Node::token(1, "x").into();
+            for _ in 0..depth {
+                handle = Node::rule(2, vec![handle]).into();
+            }
+            Cst::new(&handle)
+        };
+        let json = serde_json::to_string(&deep(62)).unwrap();
+        assert_eq!(serde_json::from_str::<Cst<ByteToken>>(&json).unwrap().len(), 63);
+
+        let json = serde_json::to_string(&deep(300)).unwrap();
+        let err = serde_json::from_str::<Cst<ByteToken>>(&json).unwrap_err();
+        assert!(err.to_string().contains("recursion limit exceeded"), "{}", err);
+    }
+}
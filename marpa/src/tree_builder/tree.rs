@@ -9,7 +9,10 @@ use std::rc::Rc;
 #[derive(Clone, Default, Debug)]
 pub struct Handle<T: Token>(Rc<RefCell<Node<T>>>);
 
+/// A node of a built tree. More kinds of node may be added, so matches
+/// outside this crate need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Node<T>
 where
     T: Token,
@@ -17,6 +20,7 @@ where
     Rule(Rule, Vec<Handle<T>>),
     Tree(Rule, Vec<Handle<T>>),
     Token(Rule, Vec<u8>),
+    Trivia(Rule, Vec<u8>),
     Leaf(T),
     Null(Symbol),
 }
@@ -49,6 +53,14 @@ where
                 }
                 write!(f, ")")?;
             }
+            Node::Trivia(ty, ref val) => {
+                write!(f, "Trivia({}, ", ty)?;
+                match ::std::str::from_utf8(val) {
+                    Ok(s) => write!(f, "{:?}", s)?,
+                    Err(_) => write!(f, "{:?}", val)?,
+                }
+                write!(f, ")")?;
+            }
             Node::Leaf(ref tok) => {
                 write!(f, "Leaf({})", tok)?;
             }
@@ -74,6 +86,10 @@ impl<T: Token> Node<T> {
         Node::Token(tok, val.into())
     }
 
+    pub fn trivia<V: Into<Vec<u8>>>(rule: Rule, val: V) -> Node<T> {
+        Node::Trivia(rule, val.into())
+    }
+
     pub fn leaf(tok: T) -> Node<T> {
         Node::Leaf(tok)
     }
@@ -114,6 +130,32 @@ impl<T: Token> Deref for Handle<T> {
     }
 }
 
+// Dropping a node drops its children first, which would recurse once per
+// level of the tree. Instead, the children of nodes that are about to go
+// away are moved out to a stack, so every node is dropped without any.
+impl<T: Token> Drop for Handle<T> {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        self.unlink(&mut stack);
+        while let Some(handle) = stack.pop() {
+            handle.unlink(&mut stack);
+        }
+    }
+}
+
+impl<T: Token> Handle<T> {
+    fn unlink(&self, out: &mut Vec<Handle<T>>) {
+        if Rc::strong_count(&self.0) > 1 {
+            return;
+        }
+        if let Ok(mut node) = self.0.try_borrow_mut() {
+            if let Node::Rule(_, ref mut chs) | Node::Tree(_, ref mut chs) = *node {
+                out.append(chs);
+            }
+        }
+    }
+}
+
 impl<T: Token> From<Node<T>> for Handle<T> {
     fn from(other: Node<T>) -> Handle<T> {
         Handle(Rc::new(RefCell::new(other)))
@@ -0,0 +1,121 @@
+extern crate marpa;
+
+use marpa::ast::{AstField, AstGrammar, AstValue, MarpaAst};
+use marpa::grammar::{Grammar, Item};
+use marpa::lexer::byte_scanner::*;
+use marpa::parser::*;
+use marpa::result::Result;
+
+use std::io::Cursor;
+
+#[derive(MarpaAst, Debug, PartialEq)]
+struct Program {
+    stmts: Vec<Stmt>,
+}
+
+#[derive(MarpaAst, Debug, PartialEq)]
+struct Stmt(Option<Expr>, Semi);
+
+#[derive(MarpaAst, Debug, PartialEq)]
+enum Expr {
+    Add(Box<Expr>, Plus, Term),
+    Term(Term),
+}
+
+#[derive(MarpaAst, Debug, PartialEq)]
+enum Term {
+    Num(Num),
+    Paren(LParen, Box<Expr>, RParen),
+}
+
+#[derive(MarpaAst, Debug, PartialEq)]
+#[marpa(token = "digits")]
+struct Num(String);
+
+#[derive(MarpaAst, Debug, PartialEq)]
+#[marpa(literal = "+")]
+struct Plus;
+
+#[derive(MarpaAst, Debug, PartialEq)]
+#[marpa(literal = ";")]
+struct Semi;
+
+#[derive(MarpaAst, Debug, PartialEq)]
+#[marpa(literal = "(")]
+struct LParen;
+
+#[derive(MarpaAst, Debug, PartialEq)]
+#[marpa(literal = ")")]
+struct RParen;
+
+// `nums ::= num ',' num` has no action, so the action for `Pair` gets both
+// of its nodes
+#[derive(Debug, PartialEq)]
+struct Pair(Num, Num);
+
+impl MarpaAst for Pair {
+    fn rules(g: &mut AstGrammar, lhs: Item) -> Result<()> {
+        let num = Num::symbol(g)?;
+        let comma = g.grammar().literal_string(None, ",")?;
+        let nums = g.grammar().rule(None, &[num, comma, num])?;
+        g.rule(lhs, &[nums], |ch| Pair(ch.take(), ch.take()))?;
+        Ok(())
+    }
+}
+
+impl AstField for Pair {
+    fn symbol(g: &mut AstGrammar) -> Result<Item> {
+        g.symbol::<Self>()
+    }
+
+    fn from_value(value: AstValue) -> Self {
+        value.take()
+    }
+}
+
+fn digits(g: &mut Grammar) -> Result<Item> {
+    let digit = g.char_range(None, '0', '9')?;
+    g.plus(None, digit)
+}
+
+fn parse(input: &str) -> Result<Program> {
+    let (g, processor) = AstGrammar::build::<Program>()?;
+    let mut p = Parser::with_grammar(g.unwrap());
+    let mut t = p.run_recognizer(ByteScanner::new(Cursor::new(input)))?;
+    Ok(processor.eval(t.next().unwrap()))
+}
+
+fn num(n: &str) -> Term {
+    Term::Num(Num(n.into()))
+}
+
+#[test]
+fn derive_ast() {
+    let program = parse("1+(2+30);;4;").unwrap();
+
+    let sum = Expr::Add(Box::new(Expr::Term(num("2"))), Plus, num("30"));
+    let expected = Program {
+        stmts: vec![
+            Stmt(
+                Some(Expr::Add(
+                    Box::new(Expr::Term(num("1"))),
+                    Plus,
+                    Term::Paren(LParen, Box::new(sum), RParen),
+                )),
+                Semi,
+            ),
+            Stmt(None, Semi),
+            Stmt(Some(Expr::Term(num("4"))), Semi),
+        ],
+    };
+
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn nodes_without_action() {
+    let (g, processor) = AstGrammar::build::<Pair>().unwrap();
+    let mut p = Parser::with_grammar(g.unwrap());
+    let mut t = p.run_recognizer(ByteScanner::new(Cursor::new("12,3"))).unwrap();
+    assert_eq!(processor.eval(t.next().unwrap()), Pair(Num("12".into()), Num("3".into())));
+}
@@ -0,0 +1,75 @@
+extern crate marpa;
+
+use marpa::grammar::{marpa_grammar, Grammar};
+use marpa::lexer::byte_scanner::*;
+use marpa::parser::*;
+use marpa::result::Result;
+use marpa::stack::processor::Processor;
+use marpa::stack::*;
+use marpa::thin::{Rule, Symbol};
+
+use std::io::Cursor;
+
+marpa_grammar! {
+    struct Calc;
+    list ::= expr* %% ',';
+    expr ::= expr '+' term => add | term;
+    term ::= num | "(" expr ")";
+    num ::= digit+;
+    digit ::= '0'..='9';
+}
+
+#[derive(Default)]
+struct Val {
+    text: Vec<u8>,
+    nums: Vec<i64>,
+}
+
+struct Eval(Calc);
+
+impl Processor for Eval {
+    type Token = ByteToken;
+    type Tree = Val;
+
+    fn proc_rule(&mut self, rule: Rule, children: Vec<Val>) -> Val {
+        let mut val = Val::default();
+        for ch in children {
+            val.text.extend(ch.text);
+            val.nums.extend(ch.nums);
+        }
+        if rule == self.0.num_0.rule() {
+            val.nums = vec![String::from_utf8(val.text.clone()).unwrap().parse().unwrap()];
+        } else if rule == self.0.add.rule() {
+            val.nums = vec![val.nums.iter().sum()];
+        }
+        val
+    }
+
+    fn proc_token(&mut self, tok: ByteToken) -> Val {
+        Val {
+            text: vec![*tok],
+            nums: vec![],
+        }
+    }
+
+    fn proc_null(&mut self, _: Symbol) -> Val {
+        Val::default()
+    }
+}
+
+fn eval(input: &str) -> Result<Vec<i64>> {
+    let mut g = Grammar::new()?;
+    let calc = Calc::new(&mut g)?;
+    let mut p = Parser::with_grammar(g.unwrap());
+    let mut t = p.run_recognizer(ByteScanner::new(Cursor::new(input)))?;
+    Ok(proc_value(Eval(calc), t.next().unwrap()).nums)
+}
+
+#[test]
+fn grammar_macro() {
+    assert_eq!(eval("1+2,(3+40)+5,").unwrap(), vec![3, 48]);
+    assert_eq!(eval("7").unwrap(), vec![7]);
+    assert_eq!(eval("").unwrap(), Vec::<i64>::new());
+    assert!(eval("1+,").is_err());
+    assert!(eval("1,,").is_err());
+}
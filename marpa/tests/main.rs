@@ -4,8 +4,10 @@ extern crate marpa;
 
 use marpa::grammar::Grammar;
 use marpa::lexer::byte_scanner::*;
+use marpa::lexer::discard::Discard;
 use marpa::parser::*;
 use marpa::result::Result;
+use marpa::scanless::{ScanlessGrammar, ScanlessParser};
 use marpa::stack::*;
 use marpa::tree_builder::*;
 
@@ -16,7 +18,23 @@ fn main() {
     real_main().unwrap();
 }
 
-fn real_main() -> Result<()> {
+#[test]
+fn lossless() {
+    real_lossless().unwrap();
+}
+
+#[test]
+fn sequence() {
+    real_sequence().unwrap();
+}
+
+#[test]
+fn discard() {
+    real_discard().unwrap();
+}
+
+// a small BNF-like grammar with whitespace between and within rules
+fn rules_grammar() -> Result<(Grammar, TreeBuilder)> {
     let mut g = Grammar::new()?;
 
     let ws_char = g.string_set(None, "\t\n\r ")?;
@@ -65,6 +83,12 @@ fn real_main() -> Result<()> {
         b.discard(d);
     }
 
+    Ok((g, b))
+}
+
+fn real_main() -> Result<()> {
+    let (g, b) = rules_grammar()?;
+
     let mut p = Parser::with_grammar(g.unwrap());
 
     let mut t = p.run_recognizer(ByteScanner::new(Cursor::new("a ::= \"test\";")))?;
@@ -73,3 +97,91 @@ fn real_main() -> Result<()> {
     println!("{}", proc_value(b, v));
     Ok(())
 }
+
+fn real_lossless() -> Result<()> {
+    let (g, mut b) = rules_grammar()?;
+    b.lossless(true);
+
+    let input = "a ::= \"test\";\nbc  ::=\t\"more\" ;\n";
+
+    let mut p = Parser::with_grammar(g.unwrap());
+
+    let mut t = p.run_recognizer(ByteScanner::new(Cursor::new(input)))?;
+    let v = t.next().unwrap();
+
+    let cst = Cst::new(&proc_value(b, v));
+    assert_eq!(cst.to_bytes(), input.as_bytes());
+
+    let tokens: Vec<&[u8]> = cst.nodes().filter(|n| !n.text().is_empty()).map(|n| n.text()).collect();
+    let expected: Vec<&[u8]> = vec![b"a", b"::=", b"\"test\"", b";", b"bc", b"::=", b"\"more\"", b";"];
+    assert_eq!(tokens, expected);
+
+    let semi = cst.nodes().find(|n| n.text() == b";").unwrap();
+    let trailing: Vec<&[u8]> = semi.trailing_trivia().map(|t| t.text).collect();
+    assert_eq!(trailing, vec![b"\n"]);
+
+    Ok(())
+}
+
+// 'a'+ separated by ',', with `nonempty` and `proper` set independently
+fn sequence_parses(input: &str, nonempty: bool, proper: bool) -> Result<bool> {
+    let mut g = Grammar::new()?;
+    let a = g.literal_string(None, "a")?;
+    let comma = g.literal_string(None, ",")?;
+    let seq = g.sequence(None, a, comma, nonempty, proper)?;
+    g.set_start(seq)?;
+    let mut p = Parser::with_grammar(g.unwrap());
+    Ok(p.run_recognizer(ByteScanner::new(Cursor::new(input))).is_ok())
+}
+
+fn real_sequence() -> Result<()> {
+    assert!(sequence_parses("a,a", true, true)?);
+    assert!(!sequence_parses("", true, true)?);
+    assert!(!sequence_parses("a,a,", true, true)?);
+    assert!(sequence_parses("", false, true)?);
+    assert!(sequence_parses("a,a,", true, false)?);
+    assert!(!sequence_parses("", true, false)?);
+    Ok(())
+}
+
+fn real_discard() -> Result<()> {
+    // the rules grammar, with alternatives and no whitespace in it
+    let mut g = ScanlessGrammar::new()?;
+    let sep_l0 = g.l0().literal_string(None, "::=")?;
+    let term_l0 = g.l0().literal_string(None, ";")?;
+    let slash_l0 = g.l0().literal_string(None, "/")?;
+    let dquote = g.l0().literal_string(None, "\"")?;
+    let not_dquote = g.l0().inverse_string_set(None, "\"")?;
+    let str_chars = g.l0().star(None, not_dquote)?;
+    let string_l0 = g.l0().rule(None, &[dquote, str_chars, dquote])?;
+    let lower = g.l0().char_range(None, 'a', 'z')?;
+    let ident_l0 = g.l0().plus(None, lower)?;
+
+    let sep = g.lexeme("sep", sep_l0)?;
+    let term = g.lexeme("term", term_l0)?;
+    let slash = g.lexeme("slash", slash_l0)?;
+    let string = g.lexeme("string", string_l0)?;
+    let ident = g.lexeme("ident", ident_l0)?;
+    let alts = g.g1().sequence(None, string, slash, true, true)?;
+    let rule = g.g1().rule(None, &[ident, sep, alts, term])?;
+    let rules = g.g1().star(None, rule)?;
+    g.g1().set_start(rules)?;
+
+    let mut d = Discard::new();
+    d.whitespace();
+    d.line_comment("//");
+    d.block_comment("/*", "*/");
+    g.discard_patterns(d);
+    let parser = ScanlessParser::new(g)?;
+
+    let input = "a ::= \"a test\" // comment\n / \"b\";\nbc/* x */::=\t\"more\" ;\n";
+    let parse = parser.parse(input.as_bytes())?;
+    let tokens: Vec<&str> = parse.lexemes().iter().map(|l| &input[l.start..l.end]).collect();
+    let expected = vec!["a", "::=", "\"a test\"", "/", "\"b\"", ";", "bc", "::=", "\"more\"", ";"];
+    assert_eq!(tokens, expected);
+
+    // discards separate lexemes, so this isn't the identifier `ab`
+    assert!(parser.parse(b"a b ::= \"x\";").is_err());
+    assert!(parser.parse(b"ab ::= \"x y\";").is_ok());
+    Ok(())
+}